@@ -0,0 +1,638 @@
+// Key bindings: `rspc bind super+shift+h node -s west`. Bound commands are run just like the ones
+// coming from rspc. Bindings can be chords (`super+w;h`), and belong to a mode (`rspc bind -m
+// resize Escape mode default`). Pointer buttons, scrolling and touchpad swipes can be bound too
+// (`rspc bind super+button2 node pointed -c`, `rspc bind swipe3_left desktop -f next`).
+
+use std::time::{Duration, Instant};
+
+use crate::types::Result;
+
+// Same bits as wlr_keyboard_modifier
+pub const MOD_SHIFT: u32 = 1 << 0;
+pub const MOD_CAPS: u32 = 1 << 1;
+pub const MOD_CTRL: u32 = 1 << 2;
+pub const MOD_ALT: u32 = 1 << 3;
+pub const MOD_MOD2: u32 = 1 << 4;
+pub const MOD_MOD3: u32 = 1 << 5;
+pub const MOD_LOGO: u32 = 1 << 6;
+pub const MOD_MOD5: u32 = 1 << 7;
+
+/// Caps lock and num lock (mod2) don't change what bindings do
+const IGNORED_MODIFIERS: u32 = MOD_CAPS | MOD_MOD2;
+
+pub const DEFAULT_MODE: &str = "default";
+
+/// Chord that isn't finished by then is forgotten
+const CHORD_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Shorter swipes don't go anywhere
+const SWIPE_THRESHOLD: f64 = 30.0;
+
+/// Used when there's no rspwlrc
+pub const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("alt+Return", "spawn foot"),
+    ("alt+h", "node -f west"),
+    ("alt+j", "node -f south"),
+    ("alt+k", "node -f north"),
+    ("alt+l", "node -f east"),
+    ("alt+shift+h", "node -s west"),
+    ("alt+shift+j", "node -s south"),
+    ("alt+shift+k", "node -s north"),
+    ("alt+shift+l", "node -s east"),
+    ("alt+t", "node -t tiled"),
+    ("alt+shift+t", "node -t pseudo_tiled"),
+    ("alt+s", "node -t ~floating"),
+    ("alt+f", "node -t ~fullscreen"),
+];
+
+// Like in X: 1 is left, 2 middle and 3 right. From linux/input-event-codes.h.
+const BUTTONS: &[(&str, u32)] = &[
+    ("button1", 0x110),
+    ("button2", 0x112),
+    ("button3", 0x111),
+    ("button8", 0x113),
+    ("button9", 0x114),
+];
+
+pub fn parse_button(name: &str) -> Option<u32> {
+    BUTTONS.iter().find(|(n, _)| *n == name).map(|(_, b)| *b)
+}
+
+pub fn button_name(button: u32) -> Option<&'static str> {
+    BUTTONS.iter().find(|(_, b)| *b == button).map(|(n, _)| *n)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollDir {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Sym(u32),
+    /// xkb keycode, the same regardless of layout
+    Code(u32),
+    /// Pointer button, like BTN_LEFT
+    Button(u32),
+    Scroll(ScrollDir),
+    /// Touchpad swipe with this many fingers
+    Swipe(u32, ScrollDir),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyCombo {
+    pub modifiers: u32,
+    pub key: Key,
+    /// Run when the key is released instead of pressed
+    pub release: bool,
+}
+
+impl KeyCombo {
+    /// `[@]mod+mod+key`, where key is a keysym name, `code:N`, `buttonN`, `scroll_up` (down,
+    /// left, right) or `swipeN_up` with N fingers. `@` means on release.
+    pub fn parse(spec: &str, keysym_from_name: impl Fn(&str) -> Option<u32>) -> Result<KeyCombo> {
+        let (release, spec) = match spec.strip_prefix('@') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+
+        let mut parts: Vec<&str> = spec.split('+').map(|x| x.trim()).collect();
+        let key = parts.pop().filter(|x| !x.is_empty());
+        let key = key.ok_or_else(|| format!("No key in {}", spec))?;
+
+        let mut modifiers = 0;
+        for m in parts {
+            modifiers |= parse_modifier(m)?;
+        }
+
+        let key = if let Some(dir) = key.strip_prefix("scroll_") {
+            Key::Scroll(parse_dir(dir)?)
+        } else if let Some(swipe) = key.strip_prefix("swipe") {
+            let (fingers, dir) = swipe
+                .split_once('_')
+                .ok_or_else(|| format!("Invalid swipe: {}", key))?;
+            match fingers.parse() {
+                // Two is scrolling
+                Ok(fingers) if fingers >= 3 => Key::Swipe(fingers, parse_dir(dir)?),
+                _ => return Err(format!("Invalid finger count: {}", fingers)),
+            }
+        } else {
+            match (key.strip_prefix("code:"), parse_button(key)) {
+                (Some(code), _) => Key::Code(
+                    code.parse()
+                        .map_err(|_| format!("Invalid keycode: {}", code))?,
+                ),
+                (None, Some(button)) => Key::Button(button),
+                (None, None) => {
+                    Key::Sym(keysym_from_name(key).ok_or_else(|| format!("Unknown key: {}", key))?)
+                }
+            }
+        };
+
+        Ok(KeyCombo {
+            modifiers,
+            key,
+            release,
+        })
+    }
+
+    /// Chord: combos separated by `;`
+    pub fn parse_chord(
+        spec: &str,
+        keysym_from_name: impl Fn(&str) -> Option<u32>,
+    ) -> Result<Vec<KeyCombo>> {
+        let chord: Vec<KeyCombo> = spec
+            .split(';')
+            .map(|combo| KeyCombo::parse(combo.trim(), &keysym_from_name))
+            .collect::<Result<_>>()?;
+        if chord.len() > 1 && chord.iter().any(|c| c.is_pointer()) {
+            return Err("Pointer can't be in a chord".to_string());
+        }
+        Ok(chord)
+    }
+
+    fn is_pointer(&self) -> bool {
+        matches!(self.key, Key::Button(_) | Key::Scroll(_) | Key::Swipe(..))
+    }
+
+    fn matches(&self, keycode: u32, modifiers: u32, sym: u32) -> bool {
+        let key_matches = match self.key {
+            Key::Sym(s) => s == sym,
+            Key::Code(c) => c == keycode,
+            Key::Button(_) | Key::Scroll(_) | Key::Swipe(..) => false,
+        };
+        key_matches && self.modifiers == modifiers & !IGNORED_MODIFIERS
+    }
+}
+
+fn parse_dir(name: &str) -> Result<ScrollDir> {
+    Ok(match name {
+        "up" => ScrollDir::Up,
+        "down" => ScrollDir::Down,
+        "left" => ScrollDir::Left,
+        "right" => ScrollDir::Right,
+        _ => return Err(format!("Invalid direction: {}", name)),
+    })
+}
+
+/// Where a swipe that moved this much went, if it went far enough
+pub fn swipe_direction(dx: f64, dy: f64) -> Option<ScrollDir> {
+    if dx.abs().max(dy.abs()) < SWIPE_THRESHOLD {
+        None
+    } else if dx.abs() > dy.abs() {
+        Some(if dx < 0.0 {
+            ScrollDir::Left
+        } else {
+            ScrollDir::Right
+        })
+    } else {
+        Some(if dy < 0.0 {
+            ScrollDir::Up
+        } else {
+            ScrollDir::Down
+        })
+    }
+}
+
+/// Pressing modifiers doesn't interrupt a chord
+fn is_modifier(sym: u32) -> bool {
+    // XKB_KEY_Shift_L..XKB_KEY_Hyper_R, XKB_KEY_ISO_Lock..XKB_KEY_ISO_Level5_Lock
+    (0xffe1..=0xffee).contains(&sym) || (0xfe01..=0xfe13).contains(&sym)
+}
+
+fn parse_modifier(name: &str) -> Result<u32> {
+    Ok(match name.to_lowercase().as_str() {
+        "shift" => MOD_SHIFT,
+        "lock" | "caps" => MOD_CAPS,
+        "ctrl" | "control" => MOD_CTRL,
+        "alt" | "mod1" => MOD_ALT,
+        "mod2" => MOD_MOD2,
+        "mod3" => MOD_MOD3,
+        "super" | "logo" | "mod4" => MOD_LOGO,
+        "mod5" => MOD_MOD5,
+        _ => return Err(format!("Unknown modifier: {}", name)),
+    })
+}
+
+pub struct Binding {
+    /// As it was given
+    pub spec: String,
+    pub mode: String,
+    pub chord: Vec<KeyCombo>,
+    pub command: String,
+}
+
+/// What to do with a key event
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeyAction {
+    /// Not ours, give it to the client
+    Pass,
+    /// Ours, but nothing to do now
+    Swallow,
+    Run(String),
+}
+
+/// Chord that has been started
+struct Chord {
+    /// Bindings that still match
+    bindings: Vec<usize>,
+    /// How many combos have been pressed
+    progress: usize,
+    started: Instant,
+}
+
+pub struct Bindings {
+    bindings: Vec<Binding>,
+    mode: String,
+    chord: Option<Chord>,
+    /// Keys whose press was a binding. Their release is ours too, and might run a command.
+    held: Vec<(u32, Option<String>)>,
+    /// Same for pointer buttons
+    held_buttons: Vec<(u32, Option<String>)>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            bindings: Vec::new(),
+            mode: DEFAULT_MODE.to_string(),
+            chord: None,
+            held: Vec::new(),
+            held_buttons: Vec::new(),
+        }
+    }
+}
+
+impl Bindings {
+    /// Replaces the old binding of the same chord in the mode
+    pub fn bind(&mut self, mode: &str, spec: &str, chord: Vec<KeyCombo>, command: String) {
+        self.chord = None;
+        self.bindings.retain(|b| b.mode != mode || b.chord != chord);
+        self.bindings.push(Binding {
+            spec: spec.to_string(),
+            mode: mode.to_string(),
+            chord,
+            command,
+        });
+    }
+
+    pub fn unbind(&mut self, mode: &str, chord: &[KeyCombo]) -> Result<()> {
+        self.chord = None;
+        let count = self.bindings.len();
+        self.bindings.retain(|b| b.mode != mode || b.chord != chord);
+        if self.bindings.len() == count {
+            return Err("No such binding".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn list(&self) -> String {
+        self.bindings
+            .iter()
+            .map(|b| match b.mode.as_str() {
+                DEFAULT_MODE => format!("{} {}\n", b.spec, b.command),
+                mode => format!("-m {} {} {}\n", mode, b.spec, b.command),
+            })
+            .collect()
+    }
+
+    pub fn mode(&self) -> &str {
+        &self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: &str) -> Result<()> {
+        if mode != DEFAULT_MODE && !self.bindings.iter().any(|b| b.mode == mode) {
+            return Err(format!("No bindings in mode {}", mode));
+        }
+        println!("Binding mode {}", mode);
+        self.mode = mode.to_string();
+        self.chord = None;
+        Ok(())
+    }
+
+    /// Candidates are (modifiers, keysym) pairs the pressed key could mean
+    pub fn press(&mut self, keycode: u32, candidates: &[(u32, u32)], now: Instant) -> KeyAction {
+        let chording = self.chord.is_some();
+        if chording && candidates.iter().any(|(_, sym)| is_modifier(*sym)) {
+            return KeyAction::Pass;
+        }
+
+        let chord = match self.chord.take() {
+            Some(chord) if now.duration_since(chord.started) < CHORD_TIMEOUT => chord,
+            _ => Chord {
+                bindings: (0..self.bindings.len())
+                    .filter(|i| self.bindings[*i].mode == self.mode)
+                    .collect(),
+                progress: 0,
+                started: now,
+            },
+        };
+
+        let matching: Vec<usize> = chord
+            .bindings
+            .iter()
+            .copied()
+            .filter(|i| {
+                let combo = &self.bindings[*i].chord[chord.progress];
+                candidates
+                    .iter()
+                    .any(|(modifiers, sym)| combo.matches(keycode, *modifiers, *sym))
+            })
+            .collect();
+
+        let progress = chord.progress + 1;
+        let complete = matching
+            .iter()
+            .map(|i| &self.bindings[*i])
+            .find(|b| b.chord.len() == progress);
+
+        match complete {
+            Some(b) if b.chord[progress - 1].release => {
+                self.held.push((keycode, Some(b.command.clone())));
+                KeyAction::Swallow
+            }
+            Some(b) => {
+                let command = b.command.clone();
+                self.held.push((keycode, None));
+                KeyAction::Run(command)
+            }
+            None if !matching.is_empty() => {
+                self.chord = Some(Chord {
+                    bindings: matching,
+                    progress,
+                    started: chord.started,
+                });
+                self.held.push((keycode, None));
+                KeyAction::Swallow
+            }
+            // Key that doesn't belong to the chord aborts it
+            None if chord.progress > 0 => {
+                println!("Chord aborted");
+                self.held.push((keycode, None));
+                KeyAction::Swallow
+            }
+            None => KeyAction::Pass,
+        }
+    }
+
+    pub fn release(&mut self, keycode: u32) -> KeyAction {
+        release_held(&mut self.held, keycode)
+    }
+
+    pub fn press_button(&mut self, button: u32, modifiers: u32) -> KeyAction {
+        match self.pointer_binding(Key::Button(button), modifiers) {
+            None => KeyAction::Pass,
+            Some(b) if b.chord[0].release => {
+                let command = b.command.clone();
+                self.held_buttons.push((button, Some(command)));
+                KeyAction::Swallow
+            }
+            Some(b) => {
+                let command = b.command.clone();
+                self.held_buttons.push((button, None));
+                KeyAction::Run(command)
+            }
+        }
+    }
+
+    pub fn release_button(&mut self, button: u32) -> KeyAction {
+        release_held(&mut self.held_buttons, button)
+    }
+
+    /// Press of the button was kept from the client, so the release will be too
+    pub fn hold_button(&mut self, button: u32) {
+        self.held_buttons.push((button, None));
+    }
+
+    /// Command for scrolling, if it's bound
+    pub fn scroll(&self, dir: ScrollDir, modifiers: u32) -> Option<String> {
+        self.pointer_binding(Key::Scroll(dir), modifiers)
+            .map(|b| b.command.clone())
+    }
+
+    /// Swipes with this many fingers are ours in any direction, when one of them is bound
+    pub fn has_swipe(&self, fingers: u32, modifiers: u32) -> bool {
+        [ScrollDir::Up, ScrollDir::Down, ScrollDir::Left, ScrollDir::Right]
+            .iter()
+            .any(|dir| self.swipe(fingers, *dir, modifiers).is_some())
+    }
+
+    pub fn swipe(&self, fingers: u32, dir: ScrollDir, modifiers: u32) -> Option<String> {
+        self.pointer_binding(Key::Swipe(fingers, dir), modifiers)
+            .map(|b| b.command.clone())
+    }
+
+    fn pointer_binding(&self, key: Key, modifiers: u32) -> Option<&Binding> {
+        self.bindings.iter().find(|b| {
+            b.mode == self.mode
+                && b.chord.len() == 1
+                && b.chord[0].key == key
+                && b.chord[0].modifiers == modifiers & !IGNORED_MODIFIERS
+        })
+    }
+}
+
+fn release_held(held: &mut Vec<(u32, Option<String>)>, code: u32) -> KeyAction {
+    match held.iter().position(|(k, _)| *k == code) {
+        None => KeyAction::Pass,
+        Some(i) => match held.remove(i).1 {
+            Some(command) => KeyAction::Run(command),
+            None => KeyAction::Swallow,
+        },
+    }
+}
+
+/// Keysyms the tests use
+#[cfg(test)]
+fn sym(name: &str) -> Option<u32> {
+    match name {
+        "h" => Some(0x68),
+        "w" => Some(0x77),
+        "Escape" => Some(0xff1b),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+fn chord(spec: &str) -> Vec<KeyCombo> {
+    KeyCombo::parse_chord(spec, sym).unwrap()
+}
+
+#[test]
+fn test_bindings() {
+    let now = Instant::now();
+    let mut bindings = Bindings::default();
+
+    let combo = KeyCombo::parse("super+shift+h", sym).unwrap();
+    assert_eq!(MOD_LOGO | MOD_SHIFT, combo.modifiers);
+    bindings.bind(
+        DEFAULT_MODE,
+        "super+shift+h",
+        vec![combo],
+        "node -s west".to_string(),
+    );
+    bindings.bind(
+        DEFAULT_MODE,
+        "@alt+code:43",
+        chord("@alt+code:43"),
+        "node -f west".to_string(),
+    );
+
+    // Caps lock on doesn't matter
+    let pressed = bindings.press(43, &[(MOD_LOGO | MOD_SHIFT | MOD_CAPS, 0x68)], now);
+    assert_eq!(KeyAction::Run("node -s west".to_string()), pressed);
+    assert_eq!(KeyAction::Swallow, bindings.release(43));
+    assert_eq!(KeyAction::Pass, bindings.release(43));
+
+    assert_eq!(
+        KeyAction::Pass,
+        bindings.press(43, &[(MOD_LOGO, 0x68)], now)
+    );
+
+    assert_eq!(
+        KeyAction::Swallow,
+        bindings.press(43, &[(MOD_ALT, 0x68)], now)
+    );
+    assert_eq!(
+        KeyAction::Run("node -f west".to_string()),
+        bindings.release(43)
+    );
+
+    assert!(KeyCombo::parse("hyper+h", sym).is_err());
+    assert!(KeyCombo::parse("super+", sym).is_err());
+    assert!(KeyCombo::parse("super+nope", sym).is_err());
+    bindings
+        .unbind(DEFAULT_MODE, &chord("super+shift+h"))
+        .unwrap();
+    assert_eq!("@alt+code:43 node -f west\n", bindings.list());
+}
+
+#[test]
+fn test_chords_and_modes() {
+    let now = Instant::now();
+    let mut bindings = Bindings::default();
+    bindings.bind(
+        DEFAULT_MODE,
+        "super + w ; h",
+        chord("super + w ; h"),
+        "node -f west".to_string(),
+    );
+    bindings.bind("resize", "h", chord("h"), "node -z left -20 0".to_string());
+    assert!(crate::command::parse_line("node -z left -20 0").is_ok());
+    bindings.bind(
+        "resize",
+        "Escape",
+        chord("Escape"),
+        "mode default".to_string(),
+    );
+
+    assert_eq!(
+        KeyAction::Swallow,
+        bindings.press(25, &[(MOD_LOGO, 0x77)], now)
+    );
+    // Letting go of super doesn't matter
+    assert_eq!(
+        KeyAction::Pass,
+        bindings.press(133, &[(MOD_LOGO, 0xffeb)], now)
+    );
+    assert_eq!(
+        KeyAction::Run("node -f west".to_string()),
+        bindings.press(43, &[(0, 0x68)], now)
+    );
+
+    // Wrong key aborts, and the next one starts over
+    assert_eq!(
+        KeyAction::Swallow,
+        bindings.press(25, &[(MOD_LOGO, 0x77)], now)
+    );
+    assert_eq!(
+        KeyAction::Swallow,
+        bindings.press(25, &[(MOD_LOGO, 0x77)], now)
+    );
+    assert_eq!(KeyAction::Pass, bindings.press(43, &[(0, 0x68)], now));
+
+    // And so does waiting too long
+    assert_eq!(
+        KeyAction::Swallow,
+        bindings.press(25, &[(MOD_LOGO, 0x77)], now)
+    );
+    let later = now + CHORD_TIMEOUT;
+    assert_eq!(KeyAction::Pass, bindings.press(43, &[(0, 0x68)], later));
+
+    assert!(bindings.set_mode("nope").is_err());
+    bindings.set_mode("resize").unwrap();
+    assert_eq!("resize", bindings.mode());
+    assert_eq!(
+        KeyAction::Run("node -z left -20 0".to_string()),
+        bindings.press(43, &[(0, 0x68)], now)
+    );
+    assert_eq!(
+        KeyAction::Run("mode default".to_string()),
+        bindings.press(9, &[(0, 0xff1b)], now)
+    );
+}
+
+#[test]
+fn test_pointer_bindings() {
+    let mut bindings = Bindings::default();
+    bindings.bind(
+        DEFAULT_MODE,
+        "super+button2",
+        chord("super+button2"),
+        "node pointed -c".to_string(),
+    );
+    bindings.bind(
+        DEFAULT_MODE,
+        "@super+button3",
+        chord("@super+button3"),
+        "node -t ~floating".to_string(),
+    );
+    bindings.bind(
+        DEFAULT_MODE,
+        "super+scroll_up",
+        chord("super+scroll_up"),
+        "desktop -f prev".to_string(),
+    );
+
+    assert_eq!(
+        KeyAction::Run("node pointed -c".to_string()),
+        bindings.press_button(0x112, MOD_LOGO)
+    );
+    assert_eq!(KeyAction::Swallow, bindings.release_button(0x112));
+    assert_eq!(KeyAction::Pass, bindings.press_button(0x112, 0));
+    assert_eq!(KeyAction::Swallow, bindings.press_button(0x111, MOD_LOGO));
+    assert_eq!(
+        KeyAction::Run("node -t ~floating".to_string()),
+        bindings.release_button(0x111)
+    );
+
+    assert_eq!(
+        Some("desktop -f prev".to_string()),
+        bindings.scroll(ScrollDir::Up, MOD_LOGO | MOD_MOD2)
+    );
+    assert_eq!(None, bindings.scroll(ScrollDir::Down, MOD_LOGO));
+    assert!(KeyCombo::parse_chord("super+w;button1", sym).is_err());
+
+    bindings.bind(
+        DEFAULT_MODE,
+        "swipe3_left",
+        chord("swipe3_left"),
+        "desktop -f next".to_string(),
+    );
+    assert!(bindings.has_swipe(3, 0));
+    assert!(!bindings.has_swipe(4, 0));
+    assert!(!bindings.has_swipe(3, MOD_LOGO));
+    assert_eq!(
+        Some("desktop -f next".to_string()),
+        bindings.swipe(3, ScrollDir::Left, 0)
+    );
+    assert_eq!(None, bindings.swipe(3, ScrollDir::Right, 0));
+    assert_eq!(Some(ScrollDir::Left), swipe_direction(-100.0, 20.0));
+    assert_eq!(Some(ScrollDir::Down), swipe_direction(10.0, 80.0));
+    assert_eq!(None, swipe_direction(5.0, -3.0));
+    assert!(KeyCombo::parse("swipe2_left", sym).is_err());
+    assert!(KeyCombo::parse("swipe3_sideways", sym).is_err());
+}
@@ -0,0 +1,945 @@
+// Commands in the style of bspc. These come from rspc (IPC) and key bindings.
+
+use crate::bindings::DEFAULT_MODE;
+use crate::rules::Rule;
+use crate::tree::Direction;
+use crate::types::{NodeId, Result};
+use crate::window_manager::{DesktopLayout, Flag, Layer, ResizeHandle, WindowState};
+use crate::wlroots_compositor::OutputId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeSelector {
+    Focused,
+    /// Under the pointer
+    Pointed,
+    Id(NodeId),
+    /// Neighbor of the focused node
+    Direction(Direction),
+    /// All marked nodes for node commands, the most recently used one elsewhere
+    Marked,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DesktopSelector {
+    Focused,
+    Next,
+    Prev,
+    /// `^n`, starting from 1
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonitorSelector {
+    Focused,
+    Id(OutputId),
+}
+
+/// Monitor or desktop that has its own value of a setting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigTarget {
+    Monitor(MonitorSelector),
+    Desktop(DesktopSelector),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeCommand {
+    Focus(NodeSelector),
+    Swap(NodeSelector),
+    State(WindowState),
+    /// `~state`: set the state, or go back to previous if already set
+    ToggleState(WindowState),
+    /// Send to desktop, and maybe follow it there
+    ToDesktop(DesktopSelector, bool),
+    /// Ask it to close
+    Close,
+    /// `-g flag[=on|off]`, None toggles
+    Flag(Flag, Option<bool>),
+    Layer(Layer),
+    /// Put to the scratchpad, or take out of it
+    Scratchpad(bool),
+    /// `-z handle dx dy`: move an edge or a corner
+    Resize(ResizeHandle, i32, i32),
+    /// `-p dir|cancel`: where the next window goes
+    Presel(Option<Direction>),
+    /// `-o ratio`: share of the preselected area
+    PreselRatio(f32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DesktopCommand {
+    Focus(DesktopSelector),
+    /// Set the layout, or None to switch to the other one
+    Layout(Option<DesktopLayout>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MonitorCommand {
+    /// Set names of the desktops
+    Desktops(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleCommand {
+    Add(Rule),
+    /// `^n` or the pattern of the rules to remove
+    Remove(String),
+    List,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindCommand {
+    /// Mode, key chord and the command it runs
+    Add(String, String, String),
+    /// Mode and key chord
+    Remove(String, String),
+    List,
+}
+
+/// Keyboard layout group to switch to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutGroup {
+    Next,
+    Prev,
+    /// Starting from 1
+    Index(u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputCommand {
+    /// Device (None is all of them), setting, and the value to set or None to get it
+    Setting(Option<String>, String, Option<String>),
+    LayoutGroup(Option<String>, LayoutGroup),
+    /// Devices and their settings
+    List,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Query {
+    /// `-N`: ids of the windows
+    Nodes,
+    /// `-T`: windows with their desktop, state and flags
+    Tree,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Node(NodeSelector, Vec<NodeCommand>),
+    Desktop(DesktopSelector, Vec<DesktopCommand>),
+    Monitor(MonitorSelector, MonitorCommand),
+    /// Get (when no value) or set a setting
+    Config(String, Option<String>),
+    /// `config -m` or `config -d`, setting of a monitor or desktop
+    LocalConfig(ConfigTarget, String, Option<String>),
+    Rule(RuleCommand),
+    Bind(BindCommand),
+    /// Switch binding mode, or tell the current one
+    Mode(Option<String>),
+    /// Program and its arguments, started as they are. Just one argument is a shell command
+    /// instead, like `spawn "foot -e htop"`.
+    Spawn(Vec<String>),
+    Input(InputCommand),
+    /// What to tell, and about which windows (None is all of them)
+    Query(Query, Option<NodeSelector>),
+    /// Show the scratchpad window on the focused desktop, or hide it
+    ToggleScratchpad,
+    /// Forget rules, bindings and settings, and run rspwlrc again
+    Reload,
+}
+
+/// Parse a command line of a binding or rspwlrc. It's split like a shell would, see split_line.
+pub fn parse_line(line: &str) -> Result<Command> {
+    parse(&split_line(line)?)
+}
+
+/// Split to arguments at whitespace. 'Single quotes' keep everything as it is, in "double quotes"
+/// and outside of quotes backslash escapes the next character.
+pub fn split_line(line: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    // None between arguments, so that '' is an empty argument and not nothing
+    let mut arg: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => args.extend(arg.take()),
+            '\'' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err("Missing closing '".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => arg.push(c),
+                            Some(c) => {
+                                arg.push('\\');
+                                arg.push(c);
+                            }
+                            None => return Err("Missing closing \"".to_string()),
+                        },
+                        Some(c) => arg.push(c),
+                        None => return Err("Missing closing \"".to_string()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => arg.get_or_insert_with(String::new).push(c),
+                None => return Err("Nothing to escape at the end".to_string()),
+            },
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+/// Join arguments to a line that split_line gives back as they were
+pub fn join_args<S: AsRef<str>>(args: &[S]) -> String {
+    let quoted: Vec<String> = args
+        .iter()
+        .map(|arg| {
+            let arg = arg.as_ref();
+            let plain = !arg.is_empty()
+                && !arg
+                    .chars()
+                    .any(|c| c.is_whitespace() || c == '\'' || c == '"' || c == '\\');
+            if plain {
+                arg.to_string()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect();
+    quoted.join(" ")
+}
+
+pub fn parse<S: AsRef<str>>(args: &[S]) -> Result<Command> {
+    let mut args = args.iter().map(|x| x.as_ref());
+    match args.next() {
+        Some("node") => parse_node(args),
+        Some("desktop") => parse_desktop(args),
+        Some("monitor") => parse_monitor(args),
+        Some("config") => parse_config(args),
+        Some("rule") => parse_rule(args),
+        Some("bind") => parse_bind(args),
+        Some("input") => parse_input(args),
+        Some("query") => parse_query(args),
+        Some("scratchpad") => match args.next() {
+            Some("-t") | Some("--toggle") | None => Ok(Command::ToggleScratchpad),
+            Some(arg) => Err(format!("Unknown scratchpad option: {}", arg)),
+        },
+        Some("reload") => Ok(Command::Reload),
+        Some("mode") => {
+            let mode = args.next().map(|x| x.to_string());
+            if args.next().is_some() {
+                return Err("Too many arguments for mode".to_string());
+            }
+            Ok(Command::Mode(mode))
+        }
+        Some("spawn") => {
+            let command: Vec<String> = args.map(|x| x.to_string()).collect();
+            if command.is_empty() {
+                return Err("Nothing to spawn".to_string());
+            }
+            Ok(Command::Spawn(command))
+        }
+        Some(cmd) => Err(format!("Unknown command: {}", cmd)),
+        None => Err("No command given".to_string()),
+    }
+}
+
+fn parse_node<'a>(args: impl Iterator<Item = &'a str>) -> Result<Command> {
+    let mut args = args.peekable();
+
+    let target = match args.next_if(|x| !x.starts_with('-')) {
+        Some(sel) => parse_node_selector(sel)?,
+        None => NodeSelector::Focused,
+    };
+
+    let mut commands = Vec::new();
+    while let Some(arg) = args.next() {
+        let command = match arg {
+            "-f" | "--focus" => match args.next_if(|x| !x.starts_with('-')) {
+                Some(sel) => NodeCommand::Focus(parse_node_selector(sel)?),
+                None => NodeCommand::Focus(target),
+            },
+            "-s" | "--swap" => NodeCommand::Swap(parse_node_selector(value(&mut args, arg)?)?),
+            "-t" | "--state" => {
+                let state = value(&mut args, arg)?;
+                match state.strip_prefix('~') {
+                    Some(state) => NodeCommand::ToggleState(parse_state(state)?),
+                    None => NodeCommand::State(parse_state(state)?),
+                }
+            }
+            "-c" | "--close" => NodeCommand::Close,
+            "-g" | "--flag" => {
+                let arg = value(&mut args, arg)?;
+                let (flag, value) = match arg.split_once('=') {
+                    Some((flag, value)) => (flag, Some(parse_bool(value)?)),
+                    None => (arg, None),
+                };
+                NodeCommand::Flag(parse_flag(flag)?, value)
+            }
+            "-l" | "--layer" => NodeCommand::Layer(parse_layer(value(&mut args, arg)?)?),
+            "-z" | "--resize" => {
+                let handle = parse_handle(value(&mut args, arg)?)?;
+                let dx = parse_pixels(value(&mut args, arg)?)?;
+                let dy = parse_pixels(value(&mut args, arg)?)?;
+                NodeCommand::Resize(handle, dx, dy)
+            }
+            "-S" | "--scratchpad" => match args.next_if(|x| !x.starts_with('-')) {
+                Some(value) => NodeCommand::Scratchpad(parse_bool(value)?),
+                None => NodeCommand::Scratchpad(true),
+            },
+            "-p" | "--presel-dir" => match value(&mut args, arg)? {
+                "cancel" => NodeCommand::Presel(None),
+                dir => NodeCommand::Presel(Some(parse_direction(dir)?)),
+            },
+            "-o" | "--presel-ratio" => {
+                let ratio = value(&mut args, arg)?;
+                match ratio.parse() {
+                    Ok(ratio) if ratio > 0.0 && ratio < 1.0 => NodeCommand::PreselRatio(ratio),
+                    _ => return Err(format!("Invalid ratio: {}", ratio)),
+                }
+            }
+            "-d" | "--to-desktop" => {
+                let desktop = parse_desktop_selector(value(&mut args, arg)?)?;
+                let follow = args.next_if(|x| *x == "--follow").is_some();
+                NodeCommand::ToDesktop(desktop, follow)
+            }
+            _ => return Err(format!("Unknown node option: {}", arg)),
+        };
+        commands.push(command);
+    }
+
+    if commands.is_empty() {
+        return Err("No node options given".to_string());
+    }
+
+    Ok(Command::Node(target, commands))
+}
+
+fn parse_desktop<'a>(args: impl Iterator<Item = &'a str>) -> Result<Command> {
+    let mut args = args.peekable();
+
+    let target = match args.next_if(|x| !x.starts_with('-')) {
+        Some(sel) => parse_desktop_selector(sel)?,
+        None => DesktopSelector::Focused,
+    };
+
+    let mut commands = Vec::new();
+    while let Some(arg) = args.next() {
+        let command = match arg {
+            "-f" | "--focus" => match args.next_if(|x| !x.starts_with('-')) {
+                Some(sel) => DesktopCommand::Focus(parse_desktop_selector(sel)?),
+                None => DesktopCommand::Focus(target.clone()),
+            },
+            "-l" | "--layout" => DesktopCommand::Layout(match value(&mut args, "--layout")? {
+                "tiled" => Some(DesktopLayout::Tiled),
+                "monocle" => Some(DesktopLayout::Monocle),
+                "next" | "prev" => None,
+                layout => return Err(format!("Invalid layout: {}", layout)),
+            }),
+            _ => return Err(format!("Unknown desktop option: {}", arg)),
+        };
+        commands.push(command);
+    }
+
+    if commands.is_empty() {
+        return Err("No desktop options given".to_string());
+    }
+
+    Ok(Command::Desktop(target, commands))
+}
+
+fn parse_monitor<'a>(args: impl Iterator<Item = &'a str>) -> Result<Command> {
+    let mut args = args.peekable();
+
+    let target = match args.next_if(|x| !x.starts_with('-')) {
+        Some(sel) => parse_monitor_selector(sel)?,
+        None => MonitorSelector::Focused,
+    };
+
+    match args.next() {
+        Some("-d") | Some("--reset-desktops") => {
+            let names: Vec<String> = args.map(|x| x.to_string()).collect();
+            if names.is_empty() {
+                return Err("No desktop names given".to_string());
+            }
+            Ok(Command::Monitor(target, MonitorCommand::Desktops(names)))
+        }
+        Some(arg) => Err(format!("Unknown monitor option: {}", arg)),
+        None => Err("No monitor options given".to_string()),
+    }
+}
+
+fn parse_query<'a>(mut args: impl Iterator<Item = &'a str>) -> Result<Command> {
+    let query = match args.next() {
+        Some("-N") | Some("--nodes") => Query::Nodes,
+        Some("-T") | Some("--tree") => Query::Tree,
+        Some(arg) => return Err(format!("Unknown query: {}", arg)),
+        None => return Err("No query given".to_string()),
+    };
+    let sel = match args.next() {
+        Some("-n") | Some("--node") => Some(parse_node_selector(value(&mut args, "--node")?)?),
+        Some(arg) => return Err(format!("Unknown query option: {}", arg)),
+        None => None,
+    };
+    if args.next().is_some() {
+        return Err("Too many arguments for query".to_string());
+    }
+    Ok(Command::Query(query, sel))
+}
+
+fn parse_config<'a>(args: impl Iterator<Item = &'a str>) -> Result<Command> {
+    let mut args = args.peekable();
+    let target = match args.next_if(|x| x.starts_with('-')) {
+        Some("-m") | Some("--monitor") => Some(ConfigTarget::Monitor(parse_monitor_selector(
+            value(&mut args, "--monitor")?,
+        )?)),
+        Some("-d") | Some("--desktop") => Some(ConfigTarget::Desktop(parse_desktop_selector(
+            value(&mut args, "--desktop")?,
+        )?)),
+        Some(arg) => return Err(format!("Unknown config option: {}", arg)),
+        None => None,
+    };
+
+    let name = args.next().ok_or_else(|| "Missing setting name".to_string())?;
+    let value = args.next().map(|x| x.to_string());
+    if args.next().is_some() {
+        return Err("Too many arguments for config".to_string());
+    }
+    Ok(match target {
+        Some(target) => Command::LocalConfig(target, name.to_string(), value),
+        None => Command::Config(name.to_string(), value),
+    })
+}
+
+fn parse_rule<'a>(mut args: impl Iterator<Item = &'a str>) -> Result<Command> {
+    match args.next() {
+        Some("-a") | Some("--add") => {
+            let pattern = value(&mut args, "--add")?;
+            let mut args = args.peekable();
+            let one_shot = args.next_if(|x| *x == "-o" || *x == "--one-shot").is_some();
+            let rule = Rule::new(pattern, one_shot, args)?;
+            Ok(Command::Rule(RuleCommand::Add(rule)))
+        }
+        Some("-r") | Some("--remove") => {
+            let sel = value(&mut args, "--remove")?;
+            Ok(Command::Rule(RuleCommand::Remove(sel.to_string())))
+        }
+        Some("-l") | Some("--list") => Ok(Command::Rule(RuleCommand::List)),
+        Some(arg) => Err(format!("Unknown rule option: {}", arg)),
+        None => Err("No rule options given".to_string()),
+    }
+}
+
+fn parse_bind<'a>(args: impl Iterator<Item = &'a str>) -> Result<Command> {
+    let mut args = args.peekable();
+
+    let mode = match args.next_if(|x| *x == "-m" || *x == "--mode") {
+        Some(option) => value(&mut args, option)?,
+        None => DEFAULT_MODE,
+    }
+    .to_string();
+
+    match args.next() {
+        Some("-r") | Some("--remove") => {
+            let chord = value(&mut args, "--remove")?;
+            Ok(Command::Bind(BindCommand::Remove(mode, chord.to_string())))
+        }
+        Some("-l") | Some("--list") => Ok(Command::Bind(BindCommand::List)),
+        Some(chord) if !chord.starts_with('-') => {
+            let command: Vec<&str> = args.collect();
+            if command.is_empty() {
+                return Err(format!("No command for {}", chord));
+            }
+            // Run with parse_line later, quoted so that the arguments stay as they are
+            Ok(Command::Bind(BindCommand::Add(
+                mode,
+                chord.to_string(),
+                join_args(&command),
+            )))
+        }
+        Some(arg) => Err(format!("Unknown bind option: {}", arg)),
+        None => Err("No bind options given".to_string()),
+    }
+}
+
+fn parse_input<'a>(args: impl Iterator<Item = &'a str>) -> Result<Command> {
+    let mut args = args.peekable();
+
+    let device = match args.next_if(|x| *x == "-d" || *x == "--device") {
+        Some(option) => Some(value(&mut args, option)?.to_string()),
+        None => None,
+    };
+
+    let command = match args.next() {
+        Some("-g") | Some("--layout-group") => {
+            let group = match value(&mut args, "--layout-group")? {
+                "next" => LayoutGroup::Next,
+                "prev" => LayoutGroup::Prev,
+                n => match n.parse() {
+                    Ok(n) if n > 0 => LayoutGroup::Index(n),
+                    _ => return Err(format!("Invalid layout group: {}", n)),
+                },
+            };
+            InputCommand::LayoutGroup(device, group)
+        }
+        Some("list") if device.is_none() => InputCommand::List,
+        Some(name) if !name.starts_with('-') => {
+            let value = args.next().map(|x| x.to_string());
+            InputCommand::Setting(device, name.to_string(), value)
+        }
+        Some(arg) => return Err(format!("Unknown input option: {}", arg)),
+        None => return Err("No input options given".to_string()),
+    };
+
+    if args.next().is_some() {
+        return Err("Too many arguments for input".to_string());
+    }
+    Ok(Command::Input(command))
+}
+
+fn value<'a>(args: &mut impl Iterator<Item = &'a str>, option: &str) -> Result<&'a str> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for {}", option))
+}
+
+fn parse_node_selector(sel: &str) -> Result<NodeSelector> {
+    match sel {
+        "focused" => return Ok(NodeSelector::Focused),
+        "pointed" => return Ok(NodeSelector::Pointed),
+        "marked" => return Ok(NodeSelector::Marked),
+        _ => (),
+    }
+    match parse_direction(sel) {
+        Ok(dir) => Ok(NodeSelector::Direction(dir)),
+        Err(_) => Ok(NodeSelector::Id(parse_id(sel)?)),
+    }
+}
+
+pub fn parse_direction(dir: &str) -> Result<Direction> {
+    Ok(match dir {
+        "west" | "left" => Direction::Left,
+        "south" | "down" => Direction::Down,
+        "north" | "up" => Direction::Up,
+        "east" | "right" => Direction::Right,
+        _ => return Err(format!("Invalid direction: {}", dir)),
+    })
+}
+
+fn parse_id(id: &str) -> Result<NodeId> {
+    let parsed = match id.strip_prefix("0x") {
+        Some(hex) => NodeId::from_str_radix(hex, 16),
+        None => id.parse(),
+    };
+    parsed.map_err(|_| format!("Invalid node selector: {}", id))
+}
+
+pub fn parse_monitor_selector(sel: &str) -> Result<MonitorSelector> {
+    match sel {
+        "focused" => Ok(MonitorSelector::Focused),
+        id => id
+            .parse()
+            .map(MonitorSelector::Id)
+            .map_err(|_| format!("Invalid monitor selector: {}", id)),
+    }
+}
+
+pub fn parse_desktop_selector(sel: &str) -> Result<DesktopSelector> {
+    Ok(match sel {
+        "focused" => DesktopSelector::Focused,
+        "next" => DesktopSelector::Next,
+        "prev" => DesktopSelector::Prev,
+        _ => match sel.strip_prefix('^') {
+            Some(index) => match index.parse() {
+                Ok(index) if index > 0 => DesktopSelector::Index(index),
+                _ => return Err(format!("Invalid desktop selector: {}", sel)),
+            },
+            None => DesktopSelector::Name(sel.to_string()),
+        },
+    })
+}
+
+pub fn parse_bool(value: &str) -> Result<bool> {
+    match value {
+        "on" | "true" => Ok(true),
+        "off" | "false" => Ok(false),
+        _ => Err(format!("Invalid boolean: {}", value)),
+    }
+}
+
+pub fn parse_flag(flag: &str) -> Result<Flag> {
+    Flag::ALL
+        .iter()
+        .copied()
+        .find(|f| f.name() == flag)
+        .ok_or_else(|| format!("Invalid flag: {}", flag))
+}
+
+pub fn parse_state(state: &str) -> Result<WindowState> {
+    Ok(match state {
+        "tiled" => WindowState::Tiled,
+        "pseudo_tiled" => WindowState::PseudoTiled,
+        "floating" => WindowState::Floating,
+        "fullscreen" => WindowState::Fullscreen,
+        _ => return Err(format!("Invalid state: {}", state)),
+    })
+}
+
+pub fn parse_layer(layer: &str) -> Result<Layer> {
+    Ok(match layer {
+        "below" => Layer::Below,
+        "normal" => Layer::Normal,
+        "above" => Layer::Above,
+        _ => return Err(format!("Invalid layer: {}", layer)),
+    })
+}
+
+fn parse_handle(handle: &str) -> Result<ResizeHandle> {
+    let (x, y) = match handle {
+        "left" => (Some(Direction::Left), None),
+        "right" => (Some(Direction::Right), None),
+        "top" => (None, Some(Direction::Up)),
+        "bottom" => (None, Some(Direction::Down)),
+        "top_left" => (Some(Direction::Left), Some(Direction::Up)),
+        "top_right" => (Some(Direction::Right), Some(Direction::Up)),
+        "bottom_left" => (Some(Direction::Left), Some(Direction::Down)),
+        "bottom_right" => (Some(Direction::Right), Some(Direction::Down)),
+        _ => return Err(format!("Invalid resize handle: {}", handle)),
+    };
+    Ok(ResizeHandle { x, y })
+}
+
+fn parse_pixels(value: &str) -> Result<i32> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid number of pixels: {}", value))
+}
+
+pub fn layer_name(layer: Layer) -> &'static str {
+    match layer {
+        Layer::Below => "below",
+        Layer::Normal => "normal",
+        Layer::Above => "above",
+    }
+}
+
+pub fn state_name(state: WindowState) -> &'static str {
+    match state {
+        WindowState::Tiled => "tiled",
+        WindowState::PseudoTiled => "pseudo_tiled",
+        WindowState::Floating => "floating",
+        WindowState::Fullscreen => "fullscreen",
+    }
+}
+
+#[test]
+fn test_parse_node() {
+    assert_eq!(
+        Command::Node(
+            NodeSelector::Focused,
+            vec![NodeCommand::ToggleState(WindowState::Floating)]
+        ),
+        parse_line("node -t ~floating").unwrap()
+    );
+    assert_eq!(
+        Command::Node(
+            NodeSelector::Id(42),
+            vec![
+                NodeCommand::State(WindowState::PseudoTiled),
+                NodeCommand::Focus(NodeSelector::Id(42))
+            ]
+        ),
+        parse_line("node 0x2a -t pseudo_tiled -f").unwrap()
+    );
+    assert_eq!(
+        Command::Node(
+            NodeSelector::Focused,
+            vec![NodeCommand::Swap(NodeSelector::Direction(Direction::Left))]
+        ),
+        parse_line("node --swap west").unwrap()
+    );
+    assert_eq!(
+        Command::Node(NodeSelector::Pointed, vec![NodeCommand::Close]),
+        parse_line("node pointed -c").unwrap()
+    );
+    assert_eq!(
+        Command::Node(
+            NodeSelector::Focused,
+            vec![
+                NodeCommand::Presel(Some(Direction::Left)),
+                NodeCommand::PreselRatio(0.3),
+                NodeCommand::Presel(None)
+            ]
+        ),
+        parse_line("node -p west -o 0.3 --presel-dir cancel").unwrap()
+    );
+    assert!(parse_line("node -p").is_err());
+    assert!(parse_line("node -p inside").is_err());
+    assert!(parse_line("node -o 1.5").is_err());
+
+    assert!(parse_line("node").is_err());
+    assert_eq!(
+        Command::Node(
+            NodeSelector::Id(5),
+            vec![
+                NodeCommand::Flag(Flag::Hidden, None),
+                NodeCommand::Flag(Flag::Hidden, Some(false))
+            ]
+        ),
+        parse_line("node 5 -g hidden --flag hidden=off").unwrap()
+    );
+    assert_eq!(
+        Command::Node(
+            NodeSelector::Marked,
+            vec![NodeCommand::Flag(Flag::Sticky, Some(true))]
+        ),
+        parse_line("node marked -g sticky=on").unwrap()
+    );
+    assert_eq!(
+        Command::Node(NodeSelector::Focused, vec![NodeCommand::Layer(Layer::Above)]),
+        parse_line("node -l above").unwrap()
+    );
+    assert!(parse_line("node -l top").is_err());
+    assert_eq!(
+        Command::Node(
+            NodeSelector::Focused,
+            vec![
+                NodeCommand::Scratchpad(true),
+                NodeCommand::Scratchpad(false),
+                NodeCommand::Close
+            ]
+        ),
+        parse_line("node -S --scratchpad off -c").unwrap()
+    );
+    assert_eq!(Command::ToggleScratchpad, parse_line("scratchpad").unwrap());
+    assert!(parse_line("scratchpad -x").is_err());
+    assert_eq!(
+        Command::Node(
+            NodeSelector::Focused,
+            vec![NodeCommand::Resize(
+                ResizeHandle {
+                    x: Some(Direction::Left),
+                    y: Some(Direction::Down)
+                },
+                -20,
+                10
+            )]
+        ),
+        parse_line("node -z bottom_left -20 10").unwrap()
+    );
+    assert!(parse_line("node -z middle 1 1").is_err());
+    assert!(parse_line("node -z left 20").is_err());
+    assert!(parse_line("node -g").is_err());
+    assert!(parse_line("node -g hidden=maybe").is_err());
+    assert!(parse_line("node -g shiny").is_err());
+    assert!(parse_line("node -t").is_err());
+    assert!(parse_line("node -t sideways").is_err());
+    assert!(parse_line("node nope -f").is_err());
+    assert!(parse_line("nodes -f").is_err());
+}
+
+#[test]
+fn test_parse_desktop() {
+    assert_eq!(
+        Command::Desktop(
+            DesktopSelector::Focused,
+            vec![DesktopCommand::Focus(DesktopSelector::Index(2))]
+        ),
+        parse_line("desktop -f ^2").unwrap()
+    );
+    assert_eq!(
+        Command::Desktop(
+            DesktopSelector::Name("web".to_string()),
+            vec![DesktopCommand::Focus(DesktopSelector::Name("web".to_string()))]
+        ),
+        parse_line("desktop web -f").unwrap()
+    );
+    assert_eq!(
+        Command::Desktop(
+            DesktopSelector::Index(1),
+            vec![DesktopCommand::Layout(Some(DesktopLayout::Monocle))]
+        ),
+        parse_line("desktop ^1 -l monocle").unwrap()
+    );
+    assert_eq!(
+        Command::Desktop(DesktopSelector::Focused, vec![DesktopCommand::Layout(None)]),
+        parse_line("desktop --layout next").unwrap()
+    );
+    assert!(parse_line("desktop -l spiral").is_err());
+    assert_eq!(
+        Command::Node(
+            NodeSelector::Focused,
+            vec![NodeCommand::ToDesktop(DesktopSelector::Next, true)]
+        ),
+        parse_line("node -d next --follow").unwrap()
+    );
+    assert_eq!(
+        Command::Monitor(
+            MonitorSelector::Focused,
+            MonitorCommand::Desktops(vec!["I".to_string(), "II".to_string()])
+        ),
+        parse_line("monitor -d I II").unwrap()
+    );
+    assert!(parse_line("desktop -f ^0").is_err());
+    assert!(parse_line("monitor -d").is_err());
+}
+
+#[test]
+fn test_parse_config() {
+    assert_eq!(
+        Command::Config("ignore_fullscreen_requests".to_string(), None),
+        parse_line("config ignore_fullscreen_requests").unwrap()
+    );
+    assert_eq!(
+        Command::Config("ignore_fullscreen_requests".to_string(), Some("all".to_string())),
+        parse_line("config ignore_fullscreen_requests all").unwrap()
+    );
+    assert_eq!(
+        Command::LocalConfig(
+            ConfigTarget::Desktop(DesktopSelector::Name("II".to_string())),
+            "top_padding".to_string(),
+            Some("20".to_string())
+        ),
+        parse_line("config -d II top_padding 20").unwrap()
+    );
+    assert_eq!(
+        Command::LocalConfig(
+            ConfigTarget::Monitor(MonitorSelector::Focused),
+            "left_padding".to_string(),
+            None
+        ),
+        parse_line("config -m focused left_padding").unwrap()
+    );
+    assert!(parse_line("config").is_err());
+    assert!(parse_line("config a b c").is_err());
+    assert!(parse_line("config -m").is_err());
+    assert!(parse_line("config -x a").is_err());
+}
+
+#[test]
+fn test_parse_rule() {
+    let Command::Rule(RuleCommand::Add(rule)) =
+        parse_line("rule -a firefox desktop=web follow=on").unwrap()
+        else { panic!("not a rule") };
+    assert!(rule.matches("firefox", "Mozilla Firefox"));
+    assert!(!rule.matches("foot", "Mozilla Firefox"));
+    assert_eq!(Some(DesktopSelector::Name("web".to_string())), rule.consequences.desktop);
+    assert_eq!(Some(true), rule.consequences.follow);
+
+    assert_eq!(Command::Rule(RuleCommand::List), parse_line("rule -l").unwrap());
+    assert_eq!(
+        Command::Rule(RuleCommand::Remove("^1".to_string())),
+        parse_line("rule -r ^1").unwrap()
+    );
+    assert!(parse_line("rule -a foot state=sideways").is_err());
+    assert!(parse_line("rule -a foot nonsense").is_err());
+    assert!(parse_line("rule -a").is_err());
+}
+
+#[test]
+fn test_parse_input() {
+    assert_eq!(
+        Command::Input(InputCommand::Setting(
+            Some("AT Translated Set 2 keyboard".to_string()),
+            "xkb_layout".to_string(),
+            Some("us,fi".to_string())
+        )),
+        parse(&["input", "-d", "AT Translated Set 2 keyboard", "xkb_layout", "us,fi"]).unwrap()
+    );
+    assert_eq!(
+        Command::Input(InputCommand::Setting(None, "repeat_rate".to_string(), None)),
+        parse_line("input repeat_rate").unwrap()
+    );
+    assert_eq!(
+        Command::Input(InputCommand::LayoutGroup(None, LayoutGroup::Index(2))),
+        parse_line("input -g 2").unwrap()
+    );
+    assert!(parse_line("input -g 0").is_err());
+    assert_eq!(
+        Command::Input(InputCommand::List),
+        parse_line("input list").unwrap()
+    );
+    assert!(parse_line("input xkb_layout us fi").is_err());
+}
+
+#[test]
+fn test_split_line() {
+    assert_eq!(
+        vec!["spawn", "foot -e 'a b'", "", "x\"y", "a\\b", "c d"],
+        split_line(r#"  spawn "foot -e 'a b'" '' "x\"y" "a\b" c\ d "#).unwrap()
+    );
+    assert!(split_line("spawn 'foot").is_err());
+    assert!(split_line("spawn \"foot").is_err());
+    assert!(split_line("spawn foot\\").is_err());
+
+    let args = ["node", "it's", "", "a \"b\"", "c\\d"];
+    assert_eq!(args.to_vec(), split_line(&join_args(&args)).unwrap());
+    assert_eq!("node -f west", join_args(&["node", "-f", "west"]));
+}
+
+#[test]
+fn test_parse_bind() {
+    assert_eq!(
+        Command::Bind(BindCommand::Add(
+            "default".to_string(),
+            "super+shift+h".to_string(),
+            "node -s west".to_string()
+        )),
+        parse(&["bind", "super+shift+h", "node", "-s", "west"]).unwrap()
+    );
+    assert_eq!(
+        Command::Bind(BindCommand::Add(
+            "resize".to_string(),
+            "super + w ; Escape".to_string(),
+            "mode default".to_string()
+        )),
+        parse(&["bind", "-m", "resize", "super + w ; Escape", "mode", "default"]).unwrap()
+    );
+    assert_eq!(
+        Command::Bind(BindCommand::Remove("resize".to_string(), "h".to_string())),
+        parse_line("bind -m resize -r h").unwrap()
+    );
+    assert_eq!(Command::Mode(None), parse_line("mode").unwrap());
+    assert_eq!(
+        Command::Spawn(vec!["foot".to_string(), "-e".to_string(), "htop".to_string()]),
+        parse_line("spawn foot -e htop").unwrap()
+    );
+    assert_eq!(
+        Command::Bind(BindCommand::Add(
+            "default".to_string(),
+            "super+Return".to_string(),
+            "spawn 'foot -e '\\''a b'\\'''".to_string()
+        )),
+        parse(&["bind", "super+Return", "spawn", "foot -e 'a b'"]).unwrap()
+    );
+    assert_eq!(
+        Command::Spawn(vec!["foot -e 'a b'".to_string()]),
+        parse_line("spawn 'foot -e '\\''a b'\\'''").unwrap()
+    );
+    assert!(parse_line("bind super+h").is_err());
+    assert!(parse_line("spawn").is_err());
+}
+
+#[test]
+fn test_parse_query() {
+    assert_eq!(Command::Query(Query::Nodes, None), parse_line("query -N").unwrap());
+    assert_eq!(
+        Command::Query(Query::Tree, Some(NodeSelector::Marked)),
+        parse_line("query -T -n marked").unwrap()
+    );
+    assert!(parse_line("query").is_err());
+    assert!(parse_line("query -N -n").is_err());
+    assert!(parse_line("query -X").is_err());
+}
@@ -0,0 +1,301 @@
+// Input device settings: `rspc input xkb_layout us,fi`, or for one device by its name or
+// identifier `rspc input -d "AT Translated Set 2 keyboard" repeat_rate 40`,
+// `rspc input -d 1267:12377:ELAN1300:00_04F3:3059_Touchpad tap on`. Device settings win over the
+// global ones.
+
+use crate::command::parse_bool;
+use crate::types::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccelProfile {
+    Flat,
+    Adaptive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollMethod {
+    None,
+    TwoFinger,
+    Edge,
+    OnButtonDown,
+}
+
+const ACCEL_PROFILES: [(&str, AccelProfile); 2] = [
+    ("flat", AccelProfile::Flat),
+    ("adaptive", AccelProfile::Adaptive),
+];
+
+const SCROLL_METHODS: [(&str, ScrollMethod); 4] = [
+    ("none", ScrollMethod::None),
+    ("two_finger", ScrollMethod::TwoFinger),
+    ("edge", ScrollMethod::Edge),
+    ("on_button_down", ScrollMethod::OnButtonDown),
+];
+
+const NAMES: [&str; 16] = [
+    "xkb_rules",
+    "xkb_model",
+    "xkb_layout",
+    "xkb_variant",
+    "xkb_options",
+    "repeat_rate",
+    "repeat_delay",
+    "tap",
+    "natural_scroll",
+    "accel_profile",
+    "accel_speed",
+    "dwt",
+    "left_handed",
+    "middle_emulation",
+    "scroll_method",
+    "map_to_output",
+];
+
+/// Settings that are not set are left as the defaults (of xkb, libinput or ours)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeviceConfig {
+    pub xkb_rules: Option<String>,
+    pub xkb_model: Option<String>,
+    pub xkb_layout: Option<String>,
+    pub xkb_variant: Option<String>,
+    pub xkb_options: Option<String>,
+    /// Keys per second
+    pub repeat_rate: Option<i32>,
+    /// Milliseconds before repeating starts
+    pub repeat_delay: Option<i32>,
+    /// Tap to click
+    pub tap: Option<bool>,
+    pub natural_scroll: Option<bool>,
+    pub accel_profile: Option<AccelProfile>,
+    /// From -1 to 1
+    pub accel_speed: Option<f64>,
+    /// Disable while typing
+    pub dwt: Option<bool>,
+    pub left_handed: Option<bool>,
+    /// Pressing left and right together is a middle click
+    pub middle_emulation: Option<bool>,
+    pub scroll_method: Option<ScrollMethod>,
+    /// Output name for touchscreens and tablets
+    pub map_to_output: Option<String>,
+}
+
+impl DeviceConfig {
+    fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        fn text(value: &str) -> Option<String> {
+            Some(value.to_string())
+        }
+        fn number(value: &str) -> Result<Option<i32>> {
+            match value.parse() {
+                Ok(n) if n >= 0 => Ok(Some(n)),
+                _ => Err(format!("Invalid number: {}", value)),
+            }
+        }
+        fn one_of<T: Copy>(names: &[(&str, T)], value: &str) -> Result<Option<T>> {
+            match names.iter().find(|(n, _)| *n == value) {
+                Some((_, x)) => Ok(Some(*x)),
+                None => Err(format!("Invalid value: {}", value)),
+            }
+        }
+        match name {
+            "xkb_rules" => self.xkb_rules = text(value),
+            "xkb_model" => self.xkb_model = text(value),
+            "xkb_layout" => self.xkb_layout = text(value),
+            "xkb_variant" => self.xkb_variant = text(value),
+            "xkb_options" => self.xkb_options = text(value),
+            "repeat_rate" => self.repeat_rate = number(value)?,
+            "repeat_delay" => self.repeat_delay = number(value)?,
+            "tap" => self.tap = Some(parse_bool(value)?),
+            "natural_scroll" => self.natural_scroll = Some(parse_bool(value)?),
+            "accel_profile" => self.accel_profile = one_of(&ACCEL_PROFILES, value)?,
+            "accel_speed" => match value.parse() {
+                Ok(speed) if (-1.0..=1.0).contains(&speed) => self.accel_speed = Some(speed),
+                _ => return Err(format!("Invalid acceleration speed: {}", value)),
+            },
+            "dwt" => self.dwt = Some(parse_bool(value)?),
+            "left_handed" => self.left_handed = Some(parse_bool(value)?),
+            "middle_emulation" => self.middle_emulation = Some(parse_bool(value)?),
+            "scroll_method" => self.scroll_method = one_of(&SCROLL_METHODS, value)?,
+            "map_to_output" => self.map_to_output = text(value),
+            _ => return Err(format!("Unknown input setting: {}", name)),
+        }
+        Ok(())
+    }
+
+    fn get(&self, name: &str) -> Result<Option<String>> {
+        fn on_off(value: Option<bool>) -> Option<String> {
+            value.map(|x| if x { "on" } else { "off" }.to_string())
+        }
+        fn name_of<T: PartialEq>(names: &[(&str, T)], value: &Option<T>) -> Option<String> {
+            let value = value.as_ref()?;
+            names
+                .iter()
+                .find(|(_, x)| x == value)
+                .map(|(n, _)| n.to_string())
+        }
+        Ok(match name {
+            "xkb_rules" => self.xkb_rules.clone(),
+            "xkb_model" => self.xkb_model.clone(),
+            "xkb_layout" => self.xkb_layout.clone(),
+            "xkb_variant" => self.xkb_variant.clone(),
+            "xkb_options" => self.xkb_options.clone(),
+            "repeat_rate" => self.repeat_rate.map(|x| x.to_string()),
+            "repeat_delay" => self.repeat_delay.map(|x| x.to_string()),
+            "tap" => on_off(self.tap),
+            "natural_scroll" => on_off(self.natural_scroll),
+            "accel_profile" => name_of(&ACCEL_PROFILES, &self.accel_profile),
+            "accel_speed" => self.accel_speed.map(|x| x.to_string()),
+            "dwt" => on_off(self.dwt),
+            "left_handed" => on_off(self.left_handed),
+            "middle_emulation" => on_off(self.middle_emulation),
+            "scroll_method" => name_of(&SCROLL_METHODS, &self.scroll_method),
+            "map_to_output" => self.map_to_output.clone(),
+            _ => return Err(format!("Unknown input setting: {}", name)),
+        })
+    }
+
+    /// Take the ones that are set in other
+    pub fn merge(&mut self, other: &DeviceConfig) {
+        fn take<T: Clone>(to: &mut Option<T>, from: &Option<T>) {
+            if from.is_some() {
+                *to = from.clone();
+            }
+        }
+        take(&mut self.xkb_rules, &other.xkb_rules);
+        take(&mut self.xkb_model, &other.xkb_model);
+        take(&mut self.xkb_layout, &other.xkb_layout);
+        take(&mut self.xkb_variant, &other.xkb_variant);
+        take(&mut self.xkb_options, &other.xkb_options);
+        take(&mut self.repeat_rate, &other.repeat_rate);
+        take(&mut self.repeat_delay, &other.repeat_delay);
+        take(&mut self.tap, &other.tap);
+        take(&mut self.natural_scroll, &other.natural_scroll);
+        take(&mut self.accel_profile, &other.accel_profile);
+        take(&mut self.accel_speed, &other.accel_speed);
+        take(&mut self.dwt, &other.dwt);
+        take(&mut self.left_handed, &other.left_handed);
+        take(&mut self.middle_emulation, &other.middle_emulation);
+        take(&mut self.scroll_method, &other.scroll_method);
+        take(&mut self.map_to_output, &other.map_to_output);
+    }
+
+    /// `name value` lines of the ones that are set
+    pub fn list(&self) -> String {
+        NAMES
+            .iter()
+            .filter_map(|name| {
+                let value = self.get(name).ok()??;
+                Some(format!("{} {}\n", name, value))
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InputConfig {
+    global: DeviceConfig,
+    devices: Vec<(String, DeviceConfig)>,
+}
+
+impl InputConfig {
+    /// Device None is the global config
+    pub fn set(&mut self, device: Option<&str>, name: &str, value: &str) -> Result<()> {
+        let config = match device {
+            None => &mut self.global,
+            Some(device) => match self.devices.iter().position(|(d, _)| d == device) {
+                Some(i) => &mut self.devices[i].1,
+                None => {
+                    self.devices
+                        .push((device.to_string(), DeviceConfig::default()));
+                    &mut self.devices.last_mut().unwrap().1
+                }
+            },
+        };
+        config.set(name, value)
+    }
+
+    /// What's in effect for the device, or the global value
+    pub fn get(&self, device: Option<&str>, name: &str) -> Result<String> {
+        let config = match device {
+            Some(device) => self.for_device(device, device),
+            None => self.global.clone(),
+        };
+        Ok(config.get(name)?.unwrap_or_default())
+    }
+
+    /// Settings can be given by device name or identifier
+    pub fn for_device(&self, name: &str, identifier: &str) -> DeviceConfig {
+        let mut config = self.global.clone();
+        for (_, c) in self
+            .devices
+            .iter()
+            .filter(|(d, _)| d == name || d == identifier)
+        {
+            config.merge(c);
+        }
+        config
+    }
+}
+
+#[test]
+fn test_input_config() {
+    let mut config = InputConfig::default();
+    config.set(None, "xkb_layout", "us,fi").unwrap();
+    config.set(None, "repeat_rate", "40").unwrap();
+    config.set(Some("kinesis"), "xkb_layout", "us").unwrap();
+
+    assert_eq!("us,fi", config.get(None, "xkb_layout").unwrap());
+    assert_eq!("us", config.get(Some("kinesis"), "xkb_layout").unwrap());
+    assert_eq!("40", config.get(Some("kinesis"), "repeat_rate").unwrap());
+    assert_eq!("", config.get(None, "xkb_options").unwrap());
+
+    let kinesis = config.for_device("kinesis", "10730:258:kinesis");
+    assert_eq!(Some("us".to_string()), kinesis.xkb_layout);
+    assert_eq!(Some(40), kinesis.repeat_rate);
+    assert_eq!(
+        Some("us,fi".to_string()),
+        config.for_device("other", "1:1:other").xkb_layout
+    );
+
+    assert!(config.set(None, "repeat_rate", "fast").is_err());
+    assert!(config.set(None, "colour", "blue").is_err());
+}
+
+#[test]
+fn test_pointer_config() {
+    let mut config = InputConfig::default();
+    let touchpad = "1267:12377:ELAN1300:00_04F3:3059_Touchpad";
+    config.set(None, "natural_scroll", "on").unwrap();
+    config.set(Some(touchpad), "tap", "on").unwrap();
+    config
+        .set(Some(touchpad), "scroll_method", "two_finger")
+        .unwrap();
+    config.set(Some(touchpad), "accel_speed", "0.5").unwrap();
+
+    let c = config.for_device("ELAN1300:00 04F3:3059 Touchpad", touchpad);
+    assert_eq!(Some(true), c.tap);
+    assert_eq!(Some(true), c.natural_scroll);
+    assert_eq!(Some(ScrollMethod::TwoFinger), c.scroll_method);
+    assert_eq!(None, config.for_device("mouse", "1:2:mouse").tap);
+    assert_eq!(
+        "two_finger",
+        config.get(Some(touchpad), "scroll_method").unwrap()
+    );
+    assert_eq!(
+        "tap on\nnatural_scroll on\naccel_speed 0.5\nscroll_method two_finger\n",
+        c.list()
+    );
+
+    config
+        .set(Some("touchscreen"), "map_to_output", "eDP-1")
+        .unwrap();
+    assert_eq!(
+        Some("eDP-1".to_string()),
+        config
+            .for_device("touchscreen", "1:1:touchscreen")
+            .map_to_output
+    );
+
+    assert!(config.set(None, "accel_speed", "2").is_err());
+    assert!(config.set(None, "accel_profile", "fast").is_err());
+    assert!(config.set(None, "tap", "maybe").is_err());
+}
@@ -1,3 +1,8 @@
+mod bindings;
+mod command;
+mod input_config;
+mod rules;
+mod settings;
 mod tree;
 mod types;
 mod window_manager;
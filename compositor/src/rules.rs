@@ -0,0 +1,201 @@
+// Window rules: `rspc rule -a APP_ID[:TITLE] [-o] CONSEQUENCE...`. Applied when a toplevel maps.
+
+use crate::command::{
+    parse_bool, parse_desktop_selector, parse_direction, parse_flag, parse_layer,
+    parse_monitor_selector, parse_state, DesktopSelector, MonitorSelector,
+};
+use crate::tree::Direction;
+use crate::types::Result;
+use crate::window_manager::{Flag, Layer, WindowState};
+
+/// What to do with a matching window. Things that aren't set are left for the defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Consequences {
+    pub desktop: Option<DesktopSelector>,
+    pub monitor: Option<MonitorSelector>,
+    pub state: Option<WindowState>,
+    pub layer: Option<Layer>,
+    /// New window goes to this side of the tiled one it's put next to
+    pub split_dir: Option<Direction>,
+    /// Portion of the split for the first (up or left) window
+    pub split_ratio: Option<f32>,
+    pub focus: Option<bool>,
+    /// Focus the desktop the window goes to
+    pub follow: Option<bool>,
+    /// Flags to set on or off, like `sticky=on`
+    pub flags: Vec<(Flag, bool)>,
+}
+
+impl Consequences {
+    /// Parse `key=value` and set it
+    pub fn set(&mut self, consequence: &str) -> Result<()> {
+        let (key, value) = consequence
+            .split_once('=')
+            .ok_or_else(|| format!("Invalid consequence: {}", consequence))?;
+        match key {
+            "desktop" => self.desktop = Some(parse_desktop_selector(value)?),
+            "monitor" => self.monitor = Some(parse_monitor_selector(value)?),
+            "state" => self.state = Some(parse_state(value)?),
+            "layer" => self.layer = Some(parse_layer(value)?),
+            "split_dir" => self.split_dir = Some(parse_direction(value)?),
+            "split_ratio" => match value.parse() {
+                Ok(ratio) if ratio > 0.0 && ratio < 1.0 => self.split_ratio = Some(ratio),
+                _ => return Err(format!("Invalid split ratio: {}", value)),
+            },
+            "focus" => self.focus = Some(parse_bool(value)?),
+            "follow" => self.follow = Some(parse_bool(value)?),
+            _ => match parse_flag(key) {
+                Ok(flag) => self.flags.push((flag, parse_bool(value)?)),
+                Err(_) => return Err(format!("Unknown consequence: {}", key)),
+            },
+        }
+        Ok(())
+    }
+
+    /// Take the ones that are set in other
+    pub fn merge(&mut self, other: &Consequences) {
+        fn take<T: Clone>(to: &mut Option<T>, from: &Option<T>) {
+            if from.is_some() {
+                *to = from.clone();
+            }
+        }
+        take(&mut self.desktop, &other.desktop);
+        take(&mut self.monitor, &other.monitor);
+        take(&mut self.state, &other.state);
+        take(&mut self.layer, &other.layer);
+        take(&mut self.split_dir, &other.split_dir);
+        take(&mut self.split_ratio, &other.split_ratio);
+        take(&mut self.focus, &other.focus);
+        take(&mut self.follow, &other.follow);
+        self.flags.extend(other.flags.iter().copied());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    /// `*` matches anything
+    pub app_id: String,
+    pub title: String,
+    /// Removed after it has been applied once
+    pub one_shot: bool,
+    pub consequences: Consequences,
+    /// As they were given, for listing
+    text: Vec<String>,
+}
+
+impl Rule {
+    pub fn new<'a>(
+        pattern: &str,
+        one_shot: bool,
+        consequences: impl Iterator<Item = &'a str>,
+    ) -> Result<Rule> {
+        let (app_id, title) = pattern.split_once(':').unwrap_or((pattern, "*"));
+        let mut rule = Rule {
+            app_id: app_id.to_string(),
+            title: title.to_string(),
+            one_shot,
+            consequences: Consequences::default(),
+            text: Vec::new(),
+        };
+        for c in consequences {
+            rule.consequences.set(c)?;
+            rule.text.push(c.to_string());
+        }
+        Ok(rule)
+    }
+
+    pub fn matches(&self, app_id: &str, title: &str) -> bool {
+        (self.app_id == "*" || self.app_id == app_id) && (self.title == "*" || self.title == title)
+    }
+
+    fn pattern(&self) -> String {
+        format!("{}:{}", self.app_id, self.title)
+    }
+}
+
+#[derive(Default)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    pub fn add(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+
+    /// Remove by `^n` (starting from 1), or all with the same pattern
+    pub fn remove(&mut self, sel: &str) -> Result<()> {
+        match sel.strip_prefix('^') {
+            Some(index) => match index.parse::<usize>() {
+                Ok(i) if i > 0 && i <= self.rules.len() => {
+                    self.rules.remove(i - 1);
+                }
+                _ => return Err(format!("No such rule: {}", sel)),
+            },
+            None => {
+                let pattern = if sel.contains(':') {
+                    sel.to_string()
+                } else {
+                    format!("{}:*", sel)
+                };
+                let count = self.rules.len();
+                self.rules.retain(|r| r.pattern() != pattern);
+                if self.rules.len() == count {
+                    return Err(format!("No such rule: {}", sel));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn list(&self) -> String {
+        self.rules
+            .iter()
+            .map(|r| {
+                let one_shot = if r.one_shot { " (one shot)" } else { "" };
+                format!("{} =>{} {}\n", r.pattern(), one_shot, r.text.join(" "))
+            })
+            .collect()
+    }
+
+    /// Consequences of all matching rules, later ones win. One shot rules are used up.
+    pub fn apply(&mut self, app_id: &str, title: &str) -> Consequences {
+        let mut consequences = Consequences::default();
+        for rule in self.rules.iter().filter(|r| r.matches(app_id, title)) {
+            println!("Rule {} matches {}", rule.pattern(), app_id);
+            consequences.merge(&rule.consequences);
+        }
+        self.rules
+            .retain(|r| !(r.one_shot && r.matches(app_id, title)));
+        consequences
+    }
+}
+
+#[test]
+fn test_rules() {
+    let mut rules = Rules::default();
+    let rule = |pattern, one_shot, c: &[&str]| Rule::new(pattern, one_shot, c.iter().copied());
+    rules.add(rule("*", false, &["state=floating", "focus=off"]).unwrap());
+    rules.add(rule("foot", false, &["state=tiled", "layer=below"]).unwrap());
+    rules.add(rule("foot:htop", true, &["desktop=^2", "sticky=on", "hidden=off"]).unwrap());
+
+    let c = rules.apply("foot", "htop");
+    assert_eq!(Some(WindowState::Tiled), c.state);
+    assert_eq!(Some(Layer::Below), c.layer);
+    assert_eq!(Some(false), c.focus);
+    assert_eq!(Some(DesktopSelector::Index(2)), c.desktop);
+    assert_eq!(vec![(Flag::Sticky, true), (Flag::Hidden, false)], c.flags);
+    assert!(rule("*", false, &["shiny=on"]).is_err());
+    assert!(rule("*", false, &["sticky=maybe"]).is_err());
+
+    // One shot rule is gone
+    assert_eq!(None, rules.apply("foot", "htop").desktop);
+
+    assert!(rules.remove("foot:htop").is_err());
+    assert!(rule("*", false, &["layer=top"]).is_err());
+    rules.remove("foot").unwrap();
+    assert_eq!("*:* => state=floating focus=off\n", rules.list());
+    rules.remove("^1").unwrap();
+    assert!(rules.remove("^1").is_err());
+    assert_eq!(Consequences::default(), rules.apply("foot", "htop"));
+}
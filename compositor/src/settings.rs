@@ -0,0 +1,325 @@
+// Settings changed with `rspc config NAME VALUE`
+
+use crate::bindings::{button_name, parse_button};
+use crate::command::parse_bool;
+use crate::types::Result;
+use crate::window_manager::Padding;
+
+/// Which of the client requests to ignore
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IgnoreRequests {
+    None,
+    All,
+    /// Ignore requests to go to the state, but let the client leave it
+    Enter,
+    /// Ignore requests to leave the state
+    Exit,
+}
+
+impl IgnoreRequests {
+    pub fn ignores(self, enter: bool) -> bool {
+        match self {
+            IgnoreRequests::None => false,
+            IgnoreRequests::All => true,
+            IgnoreRequests::Enter => enter,
+            IgnoreRequests::Exit => !enter,
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        Ok(match value {
+            "none" => IgnoreRequests::None,
+            "all" => IgnoreRequests::All,
+            "enter" => IgnoreRequests::Enter,
+            "exit" => IgnoreRequests::Exit,
+            _ => return Err(format!("Invalid value: {}", value)),
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            IgnoreRequests::None => "none",
+            IgnoreRequests::All => "all",
+            IgnoreRequests::Enter => "enter",
+            IgnoreRequests::Exit => "exit",
+        }
+    }
+}
+
+/// Which pointer buttons focus the window that is clicked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClickToFocus {
+    None,
+    Any,
+    Button(u32),
+}
+
+impl ClickToFocus {
+    pub fn focuses(self, button: u32) -> bool {
+        match self {
+            ClickToFocus::None => false,
+            ClickToFocus::Any => true,
+            ClickToFocus::Button(b) => b == button,
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        Ok(match value {
+            "none" => ClickToFocus::None,
+            "any" => ClickToFocus::Any,
+            _ => ClickToFocus::Button(
+                parse_button(value).ok_or_else(|| format!("Invalid value: {}", value))?,
+            ),
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ClickToFocus::None => "none",
+            ClickToFocus::Any => "any",
+            ClickToFocus::Button(b) => button_name(b).unwrap_or("?"),
+        }
+    }
+}
+
+/// Which window gets the focus when the focused one is closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FocusOnClose {
+    /// Most recently used window of the workspace
+    Mru,
+    /// Window that takes the space of the closed one, like bspwm
+    Sibling,
+}
+
+impl FocusOnClose {
+    fn parse(value: &str) -> Result<Self> {
+        Ok(match value {
+            "mru" => FocusOnClose::Mru,
+            "sibling" => FocusOnClose::Sibling,
+            _ => return Err(format!("Invalid value: {}", value)),
+        })
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            FocusOnClose::Mru => "mru",
+            FocusOnClose::Sibling => "sibling",
+        }
+    }
+}
+
+/// RGBA, from #rrggbb or #rrggbbaa
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color(pub [f32; 4]);
+
+impl Color {
+    fn parse(value: &str) -> Result<Self> {
+        let invalid = || format!("Invalid color: {}", value);
+        let hex = value.strip_prefix('#').ok_or_else(invalid)?;
+        if (hex.len() != 6 && hex.len() != 8) || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut color = [1.0; 4];
+        for (i, c) in color.iter_mut().enumerate().take(hex.len() / 2) {
+            let byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+            *c = byte as f32 / 255.0;
+        }
+        Ok(Color(color))
+    }
+
+    fn name(self) -> String {
+        let [r, g, b, a] = self.0.map(|c| (c * 255.0).round() as u8);
+        if a == 255 {
+            format!("#{:02x}{:02x}{:02x}", r, g, b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+        }
+    }
+}
+
+pub struct Settings {
+    pub ignore_fullscreen_requests: IgnoreRequests,
+    pub ignore_maximize_requests: IgnoreRequests,
+    /// Program that tells what to do with new windows, in addition to rules. Empty if none.
+    pub external_rules_command: String,
+    pub click_to_focus: ClickToFocus,
+    /// Click that focuses a window isn't given to it
+    pub swallow_first_click: bool,
+    /// Window under the pointer gets focused when the pointer moves
+    pub focus_follows_pointer: bool,
+    /// Focusing a window with a command puts the pointer in its middle
+    pub pointer_follows_focus: bool,
+    pub focus_on_close: FocusOnClose,
+    pub border_width: u32,
+    /// Window with keyboard focus
+    pub focused_border_color: Color,
+    /// Active window of a monitor that isn't focused
+    pub active_border_color: Color,
+    pub normal_border_color: Color,
+    pub urgent_border_color: Color,
+    /// Show where the next window goes when a window is preselected
+    pub presel_feedback: bool,
+    pub presel_feedback_color: Color,
+    /// Negative makes borders of tiled windows overlap
+    pub window_gap: i32,
+    /// Padding of all monitors. Monitors and desktops also have their own.
+    pub padding: Padding,
+    pub gapless_monocle: bool,
+    pub borderless_monocle: bool,
+    pub single_monocle: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            ignore_fullscreen_requests: IgnoreRequests::None,
+            ignore_maximize_requests: IgnoreRequests::None,
+            external_rules_command: String::new(),
+            click_to_focus: ClickToFocus::Any,
+            swallow_first_click: false,
+            focus_follows_pointer: false,
+            pointer_follows_focus: false,
+            focus_on_close: FocusOnClose::Mru,
+            border_width: 1,
+            focused_border_color: Color([0.506, 0.498, 0.498, 1.0]),
+            active_border_color: Color([0.278, 0.275, 0.271, 1.0]),
+            normal_border_color: Color([0.188, 0.188, 0.184, 1.0]),
+            urgent_border_color: Color([0.957, 0.843, 0.459, 1.0]),
+            presel_feedback: true,
+            presel_feedback_color: Color([0.957, 0.843, 0.459, 1.0]),
+            window_gap: 0,
+            padding: Padding::default(),
+            gapless_monocle: false,
+            borderless_monocle: false,
+            single_monocle: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn get(&self, name: &str) -> Result<String> {
+        Ok(match name {
+            "ignore_fullscreen_requests" => self.ignore_fullscreen_requests.name().to_string(),
+            "ignore_maximize_requests" => self.ignore_maximize_requests.name().to_string(),
+            "external_rules_command" => self.external_rules_command.clone(),
+            "click_to_focus" => self.click_to_focus.name().to_string(),
+            "swallow_first_click" => self.swallow_first_click.to_string(),
+            "focus_follows_pointer" => self.focus_follows_pointer.to_string(),
+            "pointer_follows_focus" => self.pointer_follows_focus.to_string(),
+            "focus_on_close" => self.focus_on_close.name().to_string(),
+            "border_width" => self.border_width.to_string(),
+            "focused_border_color" => self.focused_border_color.name(),
+            "active_border_color" => self.active_border_color.name(),
+            "normal_border_color" => self.normal_border_color.name(),
+            "urgent_border_color" => self.urgent_border_color.name(),
+            "presel_feedback" => self.presel_feedback.to_string(),
+            "presel_feedback_color" => self.presel_feedback_color.name(),
+            "window_gap" => self.window_gap.to_string(),
+            "gapless_monocle" => self.gapless_monocle.to_string(),
+            "borderless_monocle" => self.borderless_monocle.to_string(),
+            "single_monocle" => self.single_monocle.to_string(),
+            _ if Padding::is_side(name) => self.padding.get(name)?,
+            _ => return Err(format!("Unknown setting: {}", name)),
+        })
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        match name {
+            "ignore_fullscreen_requests" => {
+                self.ignore_fullscreen_requests = IgnoreRequests::parse(value)?
+            }
+            "ignore_maximize_requests" => {
+                self.ignore_maximize_requests = IgnoreRequests::parse(value)?
+            }
+            "external_rules_command" => self.external_rules_command = value.to_string(),
+            "click_to_focus" => self.click_to_focus = ClickToFocus::parse(value)?,
+            "swallow_first_click" => self.swallow_first_click = parse_bool(value)?,
+            "focus_follows_pointer" => self.focus_follows_pointer = parse_bool(value)?,
+            "pointer_follows_focus" => self.pointer_follows_focus = parse_bool(value)?,
+            "focus_on_close" => self.focus_on_close = FocusOnClose::parse(value)?,
+            "border_width" => {
+                self.border_width = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value: {}", value))?
+            }
+            "focused_border_color" => self.focused_border_color = Color::parse(value)?,
+            "active_border_color" => self.active_border_color = Color::parse(value)?,
+            "normal_border_color" => self.normal_border_color = Color::parse(value)?,
+            "urgent_border_color" => self.urgent_border_color = Color::parse(value)?,
+            "presel_feedback" => self.presel_feedback = parse_bool(value)?,
+            "presel_feedback_color" => self.presel_feedback_color = Color::parse(value)?,
+            "window_gap" => {
+                self.window_gap = value
+                    .parse()
+                    .map_err(|_| format!("Invalid value: {}", value))?
+            }
+            "gapless_monocle" => self.gapless_monocle = parse_bool(value)?,
+            "borderless_monocle" => self.borderless_monocle = parse_bool(value)?,
+            "single_monocle" => self.single_monocle = parse_bool(value)?,
+            _ if Padding::is_side(name) => self.padding.set(name, value)?,
+            _ => return Err(format!("Unknown setting: {}", name)),
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_settings() {
+    let mut settings = Settings::default();
+    assert_eq!("none", settings.get("ignore_fullscreen_requests").unwrap());
+
+    settings.set("ignore_fullscreen_requests", "enter").unwrap();
+    assert_eq!("enter", settings.get("ignore_fullscreen_requests").unwrap());
+    assert!(settings.ignore_fullscreen_requests.ignores(true));
+    assert!(!settings.ignore_fullscreen_requests.ignores(false));
+
+    assert!(settings.set("ignore_fullscreen_requests", "sometimes").is_err());
+    assert!(settings.set("no_such_thing", "all").is_err());
+    assert!(settings.get("no_such_thing").is_err());
+
+    settings.set("external_rules_command", "/bin/rules").unwrap();
+    assert_eq!("/bin/rules", settings.get("external_rules_command").unwrap());
+
+    settings.set("click_to_focus", "button1").unwrap();
+    assert_eq!("button1", settings.get("click_to_focus").unwrap());
+    assert!(settings.click_to_focus.focuses(0x110));
+    assert!(!settings.click_to_focus.focuses(0x111));
+    assert!(settings.set("click_to_focus", "button42").is_err());
+
+    settings.set("focus_follows_pointer", "on").unwrap();
+    assert_eq!("true", settings.get("focus_follows_pointer").unwrap());
+    assert_eq!("false", settings.get("pointer_follows_focus").unwrap());
+
+    assert_eq!("mru", settings.get("focus_on_close").unwrap());
+    settings.set("focus_on_close", "sibling").unwrap();
+    assert_eq!(FocusOnClose::Sibling, settings.focus_on_close);
+    assert!(settings.set("focus_on_close", "random").is_err());
+
+    assert_eq!("1", settings.get("border_width").unwrap());
+    settings.set("border_width", "3").unwrap();
+    assert_eq!(3, settings.border_width);
+    assert!(settings.set("border_width", "-1").is_err());
+
+    assert_eq!("#817f7f", settings.get("focused_border_color").unwrap());
+    settings.set("normal_border_color", "#ff000080").unwrap();
+    assert_eq!("#ff000080", settings.get("normal_border_color").unwrap());
+    settings.set("urgent_border_color", "#00FF00").unwrap();
+    assert_eq!(Color([0.0, 1.0, 0.0, 1.0]), settings.urgent_border_color);
+    assert!(settings.set("active_border_color", "red").is_err());
+    assert_eq!("#f4d775", settings.get("presel_feedback_color").unwrap());
+    settings.set("presel_feedback", "off").unwrap();
+    assert!(!settings.presel_feedback);
+    assert!(settings.set("active_border_color", "#12345").is_err());
+    assert!(settings.set("active_border_color", "#gg0000").is_err());
+
+    settings.set("window_gap", "-2").unwrap();
+    assert_eq!("-2", settings.get("window_gap").unwrap());
+    settings.set("top_padding", "20").unwrap();
+    assert_eq!("20", settings.get("top_padding").unwrap());
+    assert_eq!(20.0, settings.padding.top);
+    assert_eq!("0", settings.get("left_padding").unwrap());
+    assert!(settings.set("left_padding", "wide").is_err());
+    settings.set("gapless_monocle", "on").unwrap();
+    assert_eq!("true", settings.get("gapless_monocle").unwrap());
+    assert_eq!("false", settings.get("single_monocle").unwrap());
+}
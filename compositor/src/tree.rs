@@ -75,17 +75,65 @@ pub enum SplitDir {
 #[derive(Debug)]
 pub struct Split<T> {
     pub dir: SplitDir,
+    /// How much of the space goes to `a`
+    pub ratio: f32,
     pub a: Rc<Node<T>>,
     pub b: Rc<Node<T>>,
 }
 
+/// How to split a node when new one is added next to it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Presel {
+    pub dir: SplitDir,
+    /// New node goes first (up or left)
+    pub first: bool,
+    /// How much of the space goes to the first node
+    pub ratio: f32,
+}
+
+impl Presel {
+    /// New node goes to this direction of the old one
+    pub fn towards(direction: Direction, ratio: f32) -> Self {
+        let (dir, first) = match direction {
+            Direction::Up => (SplitDir::H, true),
+            Direction::Down => (SplitDir::H, false),
+            Direction::Left => (SplitDir::V, true),
+            Direction::Right => (SplitDir::V, false),
+        };
+        Presel { dir, first, ratio }
+    }
+
+    /// How much of the space goes to the new node
+    pub fn new_share(&self) -> f32 {
+        if self.first {
+            self.ratio
+        } else {
+            1.0 - self.ratio
+        }
+    }
+
+    pub fn set_new_share(&mut self, share: f32) {
+        self.ratio = if self.first { share } else { 1.0 - share };
+    }
+}
+
+impl From<SplitDir> for Presel {
+    fn from(dir: SplitDir) -> Self {
+        Presel {
+            dir,
+            first: false,
+            ratio: 0.5,
+        }
+    }
+}
+
 fn invalid_tree_op() -> ! {
     panic!("Tree state is invalid")
 }
 
 pub fn create_root<T>() -> Rc<Node<T>> {
     Rc::new(Node {
-        id: 0,
+        id: id_gen(),
         parent: RefCell::new(None),
         n: RefCell::new(N::Placeholder),
         rect: RefCell::new(Rect::default()),
@@ -95,7 +143,7 @@ pub fn create_root<T>() -> Rc<Node<T>> {
 pub fn add_leaf<T>(
     target_node: Rc<Node<T>>,
     content: impl FnOnce(NodeId) -> T,
-    split_dir: SplitDir,
+    presel: impl Into<Presel>,
 ) -> (Rc<Node<T>>, Rc<Node<T>>) {
     let is_placeholder = match &*target_node.n.borrow() {
         N::Placeholder => true,
@@ -119,22 +167,29 @@ pub fn add_leaf<T>(
             n: RefCell::new(new_n),
             rect: RefCell::new(Rect::default()),
         });
-        let split = split_from(target_node, new_node.clone(), split_dir);
+        let split = split_from(target_node, new_node.clone(), presel.into());
 
         (split, new_node)
     }
 }
 
-fn split_from<T>(tree_node: Rc<Node<T>>, new_node: Rc<Node<T>>, dir: SplitDir) -> Rc<Node<T>> {
+fn split_from<T>(tree_node: Rc<Node<T>>, new_node: Rc<Node<T>>, presel: Presel) -> Rc<Node<T>> {
     let parent = tree_node.parent();
 
+    let (a, b) = if presel.first {
+        (new_node.clone(), tree_node.clone())
+    } else {
+        (tree_node.clone(), new_node.clone())
+    };
+
     let new_split = Rc::new(Node {
         id: id_gen(),
         parent: tree_node.parent.clone(),
         n: RefCell::new(N::Split(Split {
-            dir,
-            a: tree_node.clone(),
-            b: new_node.clone(),
+            dir: presel.dir,
+            ratio: presel.ratio,
+            a,
+            b,
         })),
         rect: RefCell::new(Rect::default()),
     });
@@ -149,35 +204,67 @@ fn split_from<T>(tree_node: Rc<Node<T>>, new_node: Rc<Node<T>>, dir: SplitDir) -
     new_split
 }
 
-/// remove node from tree, return new (possibly changed) root
-pub fn remove_from_tree<T>(node: Rc<Node<T>>) -> Result<Rc<Node<T>>> {
-    fn sibling<T>(s: &Split<T>, child: &Node<T>) -> Rc<Node<T>> {
-        if s.a.id == child.id {
-            s.b.clone()
-        } else if s.b.id == child.id {
-            s.a.clone()
-        } else {
-            invalid_tree_op();
-        }
+/// put a detached leaf back to the tree next to target node, return the new parent (or the leaf
+/// itself if the target was an empty root)
+pub fn insert<T>(
+    target_node: Rc<Node<T>>,
+    node: Rc<Node<T>>,
+    presel: impl Into<Presel>,
+) -> Rc<Node<T>> {
+    if matches!(&*target_node.n.borrow(), N::Placeholder) {
+        *node.parent.borrow_mut() = None;
+        node
+    } else {
+        split_from(target_node, node, presel.into())
+    }
+}
+
+fn sibling<T>(s: &Split<T>, child: &Node<T>) -> Rc<Node<T>> {
+    if s.a.id == child.id {
+        s.b.clone()
+    } else if s.b.id == child.id {
+        s.a.clone()
+    } else {
+        invalid_tree_op();
+    }
+}
+
+/// node that takes the place of this one if it's removed
+pub fn sibling_of<T>(node: &Node<T>) -> Option<Rc<Node<T>>> {
+    let parent = node.parent()?;
+    let n = parent.n.borrow();
+    match &*n {
+        N::Split(split) => Some(sibling(split, node)),
+        _ => invalid_tree_op(),
     }
+}
 
+/// take leaf out of the tree without destroying it, return new root of the remaining tree (None
+/// if the leaf was the whole tree)
+pub fn detach<T>(node: Rc<Node<T>>) -> Result<Option<Rc<Node<T>>>> {
     match &*node.n.borrow() {
         N::Leaf(_) => (),
-        _ => return Err("Only leaf can be removed".to_string()),
+        _ => return Err("Only leaf can be detached".to_string()),
     }
 
-    if let Some(ref parent) = node.parent() {
-        match &*parent.n.borrow() {
-            N::Split(split) => {
-                let sibling = sibling(&split, &node);
-                replace_node(parent, sibling.clone());
-                Ok(sibling.root())
-            }
-            _ => invalid_tree_op(),
+    let Some(parent) = node.parent() else { return Ok(None); };
+    let sibling = match &*parent.n.borrow() {
+        N::Split(split) => sibling(split, &node),
+        _ => invalid_tree_op(),
+    };
+    replace_node(&parent, sibling.clone());
+    *node.parent.borrow_mut() = None;
+    Ok(Some(sibling.root()))
+}
+
+/// remove node from tree, return new (possibly changed) root
+pub fn remove_from_tree<T>(node: Rc<Node<T>>) -> Result<Rc<Node<T>>> {
+    match detach(node.clone())? {
+        Some(root) => Ok(root),
+        None => {
+            *node.n.borrow_mut() = N::Placeholder;
+            Ok(node)
         }
-    } else {
-        *node.n.borrow_mut() = N::Placeholder;
-        Ok(node.root())
     }
 }
 
@@ -206,6 +293,9 @@ fn replace_child<T>(parent: &Rc<Node<T>>, from: &Rc<Node<T>>, to: Rc<Node<T>>) {
 }
 
 pub fn swap<T>(node1: Rc<Node<T>>, node2: Rc<Node<T>>) {
+    if node1.id == node2.id {
+        return;
+    }
     let Some(parent1) = node1.parent() else { return; };
     let Some(parent2) = node2.parent() else { return; };
 
@@ -231,6 +321,40 @@ pub enum Direction {
     Left,
 }
 
+/// Split ratios don't go closer to the edges than this
+const MIN_RATIO: f32 = 0.05;
+
+/// Move the edge of the node on the given side by delta pixels (positive is right or down), by
+/// changing the ratio of the split that the edge divides. False if the edge is on the border of the
+/// whole tree.
+pub fn move_edge<T>(node: &Node<T>, side: Direction, delta: f32) -> bool {
+    let split_dir = match side {
+        Direction::Up | Direction::Down => SplitDir::H,
+        Direction::Left | Direction::Right => SplitDir::V,
+    };
+    let up_or_left = side == Direction::Up || side == Direction::Left;
+
+    let split = node.ancestors().find(|(parent, child_id)| match &*parent.n.borrow() {
+        N::Split(s) if s.dir == split_dir => {
+            let child = if up_or_left { &s.b } else { &s.a };
+            child.id == *child_id
+        }
+        _ => false,
+    });
+    let Some((split, _)) = split else { return false; };
+
+    let length = match split_dir {
+        SplitDir::H => split.rect.borrow().h,
+        SplitDir::V => split.rect.borrow().w,
+    };
+    if let N::Split(s) = &mut *split.n.borrow_mut() {
+        if length > 0.0 {
+            s.ratio = (s.ratio + delta / length).clamp(MIN_RATIO, 1.0 - MIN_RATIO);
+        }
+    }
+    true
+}
+
 pub fn nodes_to_direction<T>(start_node: &Node<T>, direction: Direction) -> HashMap<NodeId, Rc<Node<T>>> {
     let split_dir = match direction {
         Direction::Up | Direction::Down => SplitDir::H,
@@ -450,3 +574,105 @@ fn test_remove() {
 
     assert!(!matches!(&*b.n.borrow(), N::Leaf(_)));
 }
+
+#[test]
+fn test_sibling_of() {
+    let r = create_root::<&'static str>();
+    let (first, _) = add_leaf(r.clone(), |_| "first", SplitDir::V);
+    let (firstsplit, a) = add_leaf(first.clone(), |_| "a", SplitDir::H);
+    let (_root, b) = add_leaf(firstsplit.clone(), |_| "b", SplitDir::H);
+
+    assert_eq!(Some(first.id), sibling_of(&a).map(|x| x.id));
+    assert_eq!(Some(a.id), sibling_of(&first).map(|x| x.id));
+    assert_eq!(Some(firstsplit.id), sibling_of(&b).map(|x| x.id));
+    assert!(sibling_of(&a.clone().root()).is_none());
+}
+
+#[test]
+fn test_detach_and_insert() {
+    let r = create_root::<&'static str>();
+    let (first, _) = add_leaf(r.clone(), |_| "first", SplitDir::V);
+    let (split, a) = add_leaf(first.clone(), |_| "a", SplitDir::H);
+
+    let root = detach(a.clone()).unwrap().unwrap();
+    assert_eq!(first.id, root.id);
+    assert!(matches!(&*a.n.borrow(), N::Leaf(_)));
+    assert_eq!(a.id, a.clone().root().id);
+
+    assert!(detach(first.clone()).unwrap().is_none());
+    assert!(matches!(&*first.n.borrow(), N::Leaf(_)));
+
+    let empty = create_root::<&'static str>();
+    let root = insert(empty, a.clone(), SplitDir::V);
+    assert_eq!(a.id, root.id);
+
+    let new_split = insert(a.clone(), first.clone(), Presel::towards(Direction::Left, 0.3));
+    assert_ne!(split.id, new_split.id);
+    assert!(matches!(&*new_split.n.borrow(), N::Split(s) if s.ratio == 0.3));
+    assert_eq!(new_split.id, first.clone().root().id);
+    assert_eq!(
+        vec![new_split.id, first.id, a.id],
+        new_split
+            .clone()
+            .self_and_descendants()
+            .map(|x| x.id)
+            .collect::<Vec<NodeId>>()
+    );
+}
+
+#[test]
+fn test_swap() {
+    let r = create_root::<&'static str>();
+    let (first, _) = add_leaf(r.clone(), |_| "first", SplitDir::V);
+    let (split1, a) = add_leaf(first.clone(), |_| "a", SplitDir::V);
+    let (split2, b) = add_leaf(a.clone(), |_| "b", SplitDir::H);
+    let ids = || {
+        split1
+            .clone()
+            .self_and_descendants()
+            .map(|x| x.id)
+            .collect::<Vec<NodeId>>()
+    };
+
+    swap(b.clone(), b.clone());
+    assert_eq!(vec![split1.id, first.id, split2.id, a.id, b.id], ids());
+
+    // Same parent
+    swap(a.clone(), b.clone());
+    assert_eq!(vec![split1.id, first.id, split2.id, b.id, a.id], ids());
+
+    // Different parents
+    swap(first.clone(), a.clone());
+    assert_eq!(vec![split1.id, a.id, split2.id, b.id, first.id], ids());
+    assert_eq!(Some(split1.id), a.parent().map(|x| x.id));
+    assert_eq!(Some(split2.id), first.parent().map(|x| x.id));
+}
+
+#[test]
+fn test_move_edge() {
+    let r = create_root::<&'static str>();
+    let (first, _) = add_leaf(r.clone(), |_| "first", SplitDir::V);
+    let (split, a) = add_leaf(first.clone(), |_| "a", SplitDir::V);
+    *split.rect.borrow_mut() = Rect {
+        x: 0.0,
+        y: 0.0,
+        w: 1000.0,
+        h: 500.0,
+    };
+    let ratio = || match &*split.n.borrow() {
+        N::Split(s) => s.ratio,
+        _ => unreachable!(),
+    };
+
+    assert!(move_edge(&a, Direction::Left, -100.0));
+    assert!((ratio() - 0.4).abs() < 1e-6);
+    assert!(move_edge(&first, Direction::Right, 200.0));
+    assert!((ratio() - 0.6).abs() < 1e-6);
+    assert!(move_edge(&first, Direction::Right, 1000.0));
+    assert_eq!(1.0 - MIN_RATIO, ratio());
+
+    // Outer edges and the wrong way of splitting
+    assert!(!move_edge(&first, Direction::Left, 10.0));
+    assert!(!move_edge(&a, Direction::Right, 10.0));
+    assert!(!move_edge(&a, Direction::Up, 10.0));
+}
@@ -4,6 +4,8 @@ pub type Result<Res> = std::result::Result<Res, String>;
 
 pub type NodeId = u32;
 
+pub type WorkspaceId = u32;
+
 #[derive(Debug, Clone)]
 pub struct Rect {
     pub x: f32,
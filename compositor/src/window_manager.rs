@@ -5,40 +5,252 @@ use std::iter::Iterator;
 use std::pin::Pin;
 use std::rc::Rc;
 
-use crate::types::{NodeId, Result, Rect};
+use crate::types::{NodeId, Result, Rect, WorkspaceId};
 use crate::wlroots_compositor::{OutputId, View};
 
 type Node = tree::Node<Window>;
 
 pub struct ViewRef<'a> {
     n: Ref<'a, tree::N<Window>>,
-    rect: Ref<'a, Rect>,
 }
 
 impl<'a> ViewRef<'a> {
     pub fn content_and_rect(&'a self) -> (&'a Pin<Box<View>>, &'a Rect) {
-        match &*self.n {
-            tree::N::Leaf(l) => (&l.content.view, &*self.rect),
-            _ => panic!("ouch"),
+        let view = &window(&self.n).view;
+        (view, view.rect())
+    }
+
+    pub fn window(&'a self) -> &'a Window {
+        window(&self.n)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowState {
+    Tiled,
+    /// Keeps its preferred size, centered inside its tile
+    PseudoTiled,
+    /// Not in the tree at all
+    Floating,
+    /// Covers the whole output, but keeps its place in the tree
+    Fullscreen,
+}
+
+impl WindowState {
+    pub fn in_tree(self) -> bool {
+        self != WindowState::Floating
+    }
+}
+
+/// Windows of a higher layer are always above the ones of a lower layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    Below,
+    Normal,
+    Above,
+}
+
+/// Edge or corner of a window that `node -z` moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeHandle {
+    /// Left or right edge
+    pub x: Option<Direction>,
+    /// Top or bottom edge
+    pub y: Option<Direction>,
+}
+
+/// Flags of a window, `node -g`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    /// Not shown and not in the tree, but not closed either
+    Hidden,
+    /// Close commands are ignored
+    Locked,
+    /// Goes along when its monitor shows another workspace
+    Sticky,
+    /// Isn't split when new windows are tiled
+    Private,
+    /// For selecting many windows at once
+    Marked,
+    Urgent,
+}
+
+impl Flag {
+    pub const ALL: [Flag; 6] = [
+        Flag::Hidden,
+        Flag::Locked,
+        Flag::Sticky,
+        Flag::Private,
+        Flag::Marked,
+        Flag::Urgent,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Flag::Hidden => "hidden",
+            Flag::Locked => "locked",
+            Flag::Sticky => "sticky",
+            Flag::Private => "private",
+            Flag::Marked => "marked",
+            Flag::Urgent => "urgent",
         }
     }
 }
 
 pub struct Window {
     pub view: Pin<Box<View>>,
-    pub workspace: usize,
+    pub workspace: WorkspaceId,
+    pub state: WindowState,
+    /// Where to go back when toggled state is toggled off
+    pub last_state: WindowState,
+    /// Position of floating window. Pseudo-tiled windows use the size of it.
+    pub floating_rect: Option<Rect>,
+    /// Client asked to be maximized. Floating window takes the whole workspace then.
+    pub maximized: bool,
+    /// Window this belongs to (dialog's main window). Stacked above it, and moves along with it.
+    pub parent: Option<NodeId>,
+    /// Not mapped windows are known, but not placed anywhere yet
+    pub mapped: bool,
+    /// Wants attention, gets its own border color
+    pub urgent: bool,
+    pub hidden: bool,
+    pub locked: bool,
+    pub sticky: bool,
+    pub private: bool,
+    pub marked: bool,
+    pub layer: Layer,
+    /// Floating window that is hidden and shown with toggle_scratchpad
+    pub scratchpad: bool,
+    /// Where the next tiled window goes next to this one
+    pub presel: Option<tree::Presel>,
+    /// Width of the border around the view, as last configured
+    pub border_width: f32,
+}
+
+impl Window {
+    /// Has a tile of its own
+    fn in_tree(&self) -> bool {
+        self.state.in_tree() && !self.hidden
+    }
+}
+
+fn window<'a>(n: &'a tree::N<Window>) -> &'a Window {
+    match n {
+        tree::N::Leaf(l) => &l.content,
+        _ => panic!("ouch"),
+    }
+}
+
+fn window_mut<'a>(n: &'a mut tree::N<Window>) -> &'a mut Window {
+    match n {
+        tree::N::Leaf(l) => &mut l.content,
+        _ => panic!("ouch"),
+    }
+}
+
+/// How tiled windows of a workspace are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DesktopLayout {
+    Tiled,
+    /// Every tiled window takes the whole area, and only the active one is shown. The tree stays
+    /// as it was, for going back to tiled.
+    Monocle,
+}
+
+/// Space left empty at the edges of the area where windows are tiled
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Padding {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
 }
 
+impl Padding {
+    fn side_mut(&mut self, name: &str) -> Option<&mut f32> {
+        match name {
+            "top_padding" => Some(&mut self.top),
+            "right_padding" => Some(&mut self.right),
+            "bottom_padding" => Some(&mut self.bottom),
+            "left_padding" => Some(&mut self.left),
+            _ => None,
+        }
+    }
+
+    pub fn is_side(name: &str) -> bool {
+        Padding::default().side_mut(name).is_some()
+    }
+
+    pub fn get(&self, name: &str) -> Result<String> {
+        let mut padding = *self;
+        let side = padding
+            .side_mut(name)
+            .ok_or_else(|| format!("Unknown setting: {}", name))?;
+        Ok(side.to_string())
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let side = self
+            .side_mut(name)
+            .ok_or_else(|| format!("Unknown setting: {}", name))?;
+        let value: i32 = value.parse().map_err(|_| format!("Invalid value: {}", value))?;
+        *side = value as f32;
+        Ok(())
+    }
+
+    fn add(self, other: Padding) -> Padding {
+        Padding {
+            top: self.top + other.top,
+            right: self.right + other.right,
+            bottom: self.bottom + other.bottom,
+            left: self.left + other.left,
+        }
+    }
+}
+
+/// Settings for how windows are laid out
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Layout {
+    /// Border of windows that aren't fullscreen
+    pub border_width: f32,
+    /// Space between tiled windows, and between them and the edges
+    pub window_gap: f32,
+    /// Padding of every monitor, in addition to their own
+    pub padding: Padding,
+    /// No gaps in monocle layout
+    pub gapless_monocle: bool,
+    /// No border in monocle layout
+    pub borderless_monocle: bool,
+    /// Workspace with one tiled window is in monocle layout
+    pub single_monocle: bool,
+}
+
+/// Desktop in bspwm terms. Output shows one of its workspaces at a time.
 pub struct Workspace {
+    pub id: WorkspaceId,
+    pub name: String,
     root: Rc<Node>,
     rect: Rect,
-    output_id: Option<OutputId>,
+    pub output_id: Option<OutputId>,
+    pub padding: Padding,
+    layout: DesktopLayout,
+}
+
+struct OutputState {
+    id: OutputId,
+    rect: Rect,
+    active_workspace: WorkspaceId,
+    padding: Padding,
 }
 
 pub struct WindowManager {
     view_nodes: HashMap<NodeId, Rc<Node>>,
     mru_view: Vec<NodeId>,
     workspaces: Vec<Workspace>,
+    outputs: Vec<OutputState>,
+    focused_workspace: WorkspaceId,
+    next_workspace_id: WorkspaceId,
+    layout: Layout,
 }
 
 pub struct OutputInfo {
@@ -46,52 +258,310 @@ pub struct OutputInfo {
     pub rect: Rect,
 }
 
+/// Where to put a window when it maps
+pub struct Placement {
+    pub parent: Option<NodeId>,
+    /// Defaults to parent's workspace, or the focused one
+    pub workspace: Option<WorkspaceId>,
+    pub state: WindowState,
+    /// Which side of the tiled window it's put next to
+    pub split_dir: Option<Direction>,
+    pub split_ratio: Option<f32>,
+    /// Be the active window of its workspace
+    pub focus: bool,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Placement {
+            parent: None,
+            workspace: None,
+            state: WindowState::Tiled,
+            split_dir: None,
+            split_ratio: None,
+            focus: true,
+        }
+    }
+}
+
 impl WindowManager {
     pub fn new() -> WindowManager {
-        let ws = Workspace {
-            root: tree::create_root(),
-            // TODO: just use node dimensions
-            rect: Rect {
-                x: 0.0,
-                y: 0.0,
-                w: 800.0,
-                h: 600.0,
-            },
-            output_id: None,
-        };
-        WindowManager {
+        let mut wm = WindowManager {
             view_nodes: HashMap::new(),
             mru_view: Vec::new(),
-            workspaces: vec![ws],
+            workspaces: Vec::new(),
+            outputs: Vec::new(),
+            focused_workspace: 1,
+            next_workspace_id: 1,
+            layout: Layout::default(),
+        };
+        // TODO: just use node dimensions
+        let rect = Rect {
+            x: 0.0,
+            y: 0.0,
+            w: 800.0,
+            h: 600.0,
+        };
+        wm.focused_workspace = wm.create_workspace(None, None, rect);
+        wm
+    }
+
+    fn create_workspace(
+        &mut self,
+        name: Option<String>,
+        output_id: Option<OutputId>,
+        rect: Rect,
+    ) -> WorkspaceId {
+        let id = self.next_workspace_id;
+        self.next_workspace_id += 1;
+        self.workspaces.push(Workspace {
+            id,
+            name: name.unwrap_or_else(|| id.to_string()),
+            root: tree::create_root(),
+            rect,
+            output_id,
+            padding: Padding::default(),
+            layout: DesktopLayout::Tiled,
+        });
+        id
+    }
+
+    pub fn workspaces(&self) -> impl Iterator<Item = &Workspace> {
+        self.workspaces.iter()
+    }
+
+    pub fn focused_workspace(&self) -> WorkspaceId {
+        self.focused_workspace
+    }
+
+    pub fn focused_output(&self) -> Option<OutputId> {
+        self.workspace(self.focused_workspace).output_id
+    }
+
+    /// Workspace the output is showing
+    pub fn output_workspace(&self, output: OutputId) -> Option<WorkspaceId> {
+        self.outputs
+            .iter()
+            .find(|o| o.id == output)
+            .map(|o| o.active_workspace)
+    }
+
+    pub fn workspace_of_view(&self, id: NodeId) -> Result<WorkspaceId> {
+        Ok(window(&self.node(id)?.n.borrow()).workspace)
+    }
+
+    fn workspace(&self, id: WorkspaceId) -> &Workspace {
+        self.workspaces
+            .iter()
+            .find(|w| w.id == id)
+            .expect("BUG: workspace missing")
+    }
+
+    fn workspace_mut(&mut self, id: WorkspaceId) -> &mut Workspace {
+        self.workspaces
+            .iter_mut()
+            .find(|w| w.id == id)
+            .expect("BUG: workspace missing")
+    }
+
+    fn check_workspace(&self, id: WorkspaceId) -> Result<()> {
+        if self.workspaces.iter().any(|w| w.id == id) {
+            Ok(())
+        } else {
+            Err(format!("No workspace {}", id))
         }
     }
 
+    /// Workspace is shown on some output
+    fn is_visible(&self, workspace: WorkspaceId) -> bool {
+        self.outputs.iter().any(|o| o.active_workspace == workspace)
+    }
+
     pub fn find_view<'a>(&'a self, id: NodeId) -> Option<ViewRef<'a>> {
         self.view_nodes.get(&id)
             .map(|node| ViewRef {
                 n: node.n.borrow(), // n MUST be Leaf. If not, will crash later
-                rect: node.rect.borrow(),
             })
     }
 
-    // TODO ren
-    pub fn views_for_render<'a>(&'a self, _output: OutputId) -> impl Iterator<Item = ViewRef<'a>> {
-        // TODO check output
+    /// All the views, visible or not
+    pub fn views<'a>(&'a self) -> impl Iterator<Item = ViewRef<'a>> {
         self.mru_view.iter().map(|id| {
             self.find_view(*id).expect("View not there where it should be")
         })
     }
 
+    // TODO ren
+    pub fn views_for_render<'a>(&'a self, output: OutputId) -> impl Iterator<Item = ViewRef<'a>> {
+        let workspace = self
+            .outputs
+            .iter()
+            .find(|o| o.id == output)
+            .map(|o| o.active_workspace);
+        let fullscreen = self.fullscreen_views();
+        let monocle = self.monocle_views();
+        self.stacking_order()
+            .into_iter()
+            .filter(move |id| {
+                Some(self.workspace_of(*id)) == workspace
+                    && !self.is_hidden(*id)
+                    && !self.covered_by_fullscreen(*id, &fullscreen)
+                    && !self.covered_by_monocle(*id, &monocle)
+            })
+            .map(move |id| {
+                self.find_view(id).expect("View not there where it should be")
+            })
+    }
+
     pub fn views_for_finding<'a>(&'a self) ->  impl Iterator<Item = ViewRef<'a>> {
-        self.mru_view.iter().rev().map(|id| {
-            self.find_view(*id).expect("View not there where it should be")
-        })
+        let fullscreen = self.fullscreen_views();
+        let monocle = self.monocle_views();
+        self.stacking_order()
+            .into_iter()
+            .rev()
+            .filter(move |id| {
+                self.is_visible(self.workspace_of(*id))
+                    && !self.is_hidden(*id)
+                    && !self.covered_by_fullscreen(*id, &fullscreen)
+                    && !self.covered_by_monocle(*id, &monocle)
+            })
+            .map(move |id| {
+                self.find_view(id).expect("View not there where it should be")
+            })
+    }
+
+    /// Bottom to top. Layer goes first, then floating is above tiled, and then MRU order. Children
+    /// are always above their parent.
+    fn stacking_order(&self) -> Vec<NodeId> {
+        let mut order = self.mru_view.clone();
+        order.sort_by_cached_key(|id| self.stacking_key(*id));
+        order
+    }
+
+    /// Rank, MRU position and depth. Child gets at least the rank and position of its parent, so
+    /// a floating dialog of a tiled window is still above other floating windows.
+    fn stacking_key(&self, id: NodeId) -> ((Layer, u8), usize, usize) {
+        let rank = self.stacking_rank(id);
+        let position = self.mru_view.iter().position(|x| *x == id).unwrap_or(0);
+        match self.parent_of(id) {
+            Some(parent) => {
+                let (parent_rank, parent_position, depth) = self.stacking_key(parent);
+                (rank.max(parent_rank), position.max(parent_position), depth + 1)
+            }
+            None => (rank, position, 0),
+        }
+    }
+
+    fn stacking_rank(&self, id: NodeId) -> (Layer, u8) {
+        let n = self.view_nodes[&id].n.borrow();
+        let w = window(&n);
+        let state = match w.state {
+            WindowState::Tiled | WindowState::PseudoTiled => 0,
+            WindowState::Floating => 1,
+            WindowState::Fullscreen => 2,
+        };
+        (w.layer, state)
+    }
+
+    fn workspace_of(&self, id: NodeId) -> WorkspaceId {
+        window(&self.view_nodes[&id].n.borrow()).workspace
+    }
+
+    fn is_hidden(&self, id: NodeId) -> bool {
+        window(&self.view_nodes[&id].n.borrow()).hidden
+    }
+
+    fn parent_of(&self, id: NodeId) -> Option<NodeId> {
+        window(&self.view_nodes[&id].n.borrow()).parent
+    }
+
+    fn children_of(&self, id: NodeId) -> Vec<NodeId> {
+        self.mru_view
+            .iter()
+            .copied()
+            .filter(|child| self.parent_of(*child) == Some(id))
+            .collect()
+    }
+
+    /// Most recently used fullscreen window for each workspace that has one
+    fn fullscreen_views(&self) -> HashMap<WorkspaceId, NodeId> {
+        let mut fullscreen = HashMap::new();
+        for id in self.mru_view.iter() {
+            let node = &self.view_nodes[id];
+            let n = node.n.borrow();
+            let w = window(&n);
+            if w.state == WindowState::Fullscreen {
+                fullscreen.insert(w.workspace, *id);
+            }
+        }
+        fullscreen
+    }
+
+    /// Fullscreen window hides everything else on the workspace, except its own dialogs
+    fn covered_by_fullscreen(&self, id: NodeId, fullscreen: &HashMap<WorkspaceId, NodeId>) -> bool {
+        let Some(fullscreen_id) = fullscreen.get(&self.workspace_of(id)) else { return false; };
+        let mut ancestor = Some(id);
+        while let Some(a) = ancestor {
+            if a == *fullscreen_id {
+                return false;
+            }
+            ancestor = self.parent_of(a);
+        }
+        true
+    }
+
+    /// Most recently used tiled window of each workspace in monocle layout, the one that is shown
+    fn monocle_views(&self) -> HashMap<WorkspaceId, NodeId> {
+        let mut shown = HashMap::new();
+        for id in self.mru_view.iter() {
+            let node = &self.view_nodes[id];
+            let n = node.n.borrow();
+            let w = window(&n);
+            if w.in_tree() && self.is_monocle(self.workspace(w.workspace)) {
+                shown.insert(w.workspace, *id);
+            }
+        }
+        shown
+    }
+
+    /// In monocle layout, the shown tiled window hides the other tiled ones
+    fn covered_by_monocle(&self, id: NodeId, monocle: &HashMap<WorkspaceId, NodeId>) -> bool {
+        let Some(shown) = monocle.get(&self.workspace_of(id)) else { return false; };
+        *shown != id && window(&self.view_nodes[&id].n.borrow()).in_tree()
+    }
+
+    fn is_monocle(&self, ws: &Workspace) -> bool {
+        ws.layout == DesktopLayout::Monocle
+            || (self.layout.single_monocle && matches!(&*ws.root.n.borrow(), tree::N::Leaf(_)))
+    }
+
+    /// Set the layout, or None to switch to the other one
+    pub fn set_desktop_layout(
+        &mut self,
+        id: WorkspaceId,
+        layout: Option<DesktopLayout>,
+    ) -> Result<()> {
+        self.check_workspace(id)?;
+        let ws = self.workspace_mut(id);
+        ws.layout = match (layout, ws.layout) {
+            (Some(layout), _) => layout,
+            (None, DesktopLayout::Tiled) => DesktopLayout::Monocle,
+            (None, DesktopLayout::Monocle) => DesktopLayout::Tiled,
+        };
+        println!("Layout of workspace {}: {:?}", id, ws.layout);
+        self.configure_views();
+        Ok(())
     }
 
     pub fn touch_node(&mut self, id: NodeId) {
         println!("Touch {}", id);
         self.remove_from_mru(id);
         self.mru_view.push(id);
+
+        let workspace = self.workspace_of(id);
+        if let Err(e) = self.focus_workspace(workspace) {
+            println!("Failed to focus workspace of {}: {}", id, e);
+        }
     }
 
     fn remove_from_mru(&mut self, id: NodeId) {
@@ -103,44 +573,245 @@ impl WindowManager {
         self.mru_view.remove(mru_idx);
     }
 
+    /// Most recently used node of the focused workspace
     pub fn active_node(&self) -> Option<Rc<Node>> {
-        self.mru_view.last()
-            .and_then(|x| self.view_nodes.get(x))
+        self.active_window(self.focused_workspace)
+            .and_then(|x| self.view_nodes.get(&x))
             .cloned()
     }
 
-    pub fn add_view(&mut self, create_view: impl FnOnce(NodeId) -> Pin<Box<View>>) -> NodeId {
-        let active_node = self.active_node().unwrap_or_else(|| self.workspaces[0].root.clone());
+    /// Most recently used window of the workspace that isn't hidden
+    pub fn active_window(&self, workspace: WorkspaceId) -> Option<NodeId> {
+        self.mru_view
+            .iter()
+            .rev()
+            .find(|id| self.workspace_of(**id) == workspace && !self.is_hidden(**id))
+            .copied()
+    }
 
-        let workspace = 0;
+    /// Show the workspace on its output, and make it the focused one
+    pub fn focus_workspace(&mut self, id: WorkspaceId) -> Result<()> {
+        self.check_workspace(id)?;
+        let output_id = self.workspace(id).output_id;
+        let mut previous = None;
+        if let Some(output) = self.outputs.iter_mut().find(|o| Some(o.id) == output_id) {
+            previous = Some(output.active_workspace).filter(|p| *p != id);
+            output.active_workspace = id;
+        }
+        self.focused_workspace = id;
 
-        let (parent, new_leaf) = tree::add_leaf(
-            active_node.clone(),
-            |id| Window {
-                view: create_view(id),
-                workspace,
-            },
-            split_dir(active_node),
-        );
+        // Sticky windows come along
+        if let Some(previous) = previous {
+            let sticky: Vec<NodeId> = self
+                .mru_view
+                .iter()
+                .copied()
+                .filter(|x| {
+                    let n = self.view_nodes[x].n.borrow();
+                    let w = window(&n);
+                    w.sticky && w.workspace == previous
+                })
+                .collect();
+            for x in sticky {
+                self.move_to_workspace(x, id)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add window that isn't mapped yet. It gets placed by map_view.
+    pub fn add_view(&mut self, create_view: impl FnOnce(NodeId) -> Pin<Box<View>>) -> NodeId {
+        let workspace = self.focused_workspace;
+        let new_window = |id| Window {
+            view: create_view(id),
+            workspace,
+            state: WindowState::Floating,
+            last_state: WindowState::Tiled,
+            floating_rect: None,
+            maximized: false,
+            parent: None,
+            mapped: false,
+            urgent: false,
+            hidden: false,
+            locked: false,
+            sticky: false,
+            private: false,
+            marked: false,
+            layer: Layer::Normal,
+            scratchpad: false,
+            presel: None,
+            border_width: 0.0,
+        };
 
-        self.workspaces[workspace].root = parent.root();
+        // Leaf without a tree
+        let (_, new_leaf) = tree::add_leaf(tree::create_root(), new_window, tree::SplitDir::V);
 
         self.view_nodes.insert(new_leaf.id, new_leaf.clone());
-        self.mru_view.push(new_leaf.id);
         println!("Added {}", new_leaf.id);
 
+        new_leaf.id
+    }
+
+    /// Put the window where it goes. Only the first map counts.
+    pub fn map_view(&mut self, id: NodeId, placement: Placement) -> Result<()> {
+        let node = self
+            .view_nodes
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| format!("No window for {}", id))?;
+        if window(&node.n.borrow()).mapped {
+            return Ok(());
+        }
+
+        let parent = placement.parent.filter(|p| self.node(*p).is_ok());
+        let workspace = match (placement.workspace, parent) {
+            (Some(workspace), _) => workspace,
+            (None, Some(p)) => self.workspace_of(p),
+            (None, None) => self.focused_workspace,
+        };
+        self.check_workspace(workspace)?;
+
+        {
+            let mut n = node.n.borrow_mut();
+            let w = window_mut(&mut n);
+            w.workspace = workspace;
+            w.state = placement.state;
+            w.parent = parent;
+            w.mapped = true;
+        }
+
+        // Size it has now is the one it wants, remember it for floating and pseudo-tiling
+        self.init_floating_rect(&node);
+
+        if placement.state.in_tree() {
+            self.put_in_tree_at(&node, workspace, placement.split_dir, placement.split_ratio);
+        }
+
+        // Not focused one goes below the active window of the workspace
+        let below = self
+            .mru_view
+            .iter()
+            .rposition(|x| self.workspace_of(*x) == workspace)
+            .filter(|_| !placement.focus);
+        match below {
+            Some(idx) => self.mru_view.insert(idx, id),
+            None => self.mru_view.push(id),
+        }
+        println!("Mapped {} to workspace {} as {:?}", id, workspace, placement.state);
+
         // TODO only configure changed views
         self.configure_views();
+        Ok(())
+    }
 
-        new_leaf.id
+    /// Most recently used tiled node of the workspace, new tiled windows go next to it. Private
+    /// ones only if there's nothing else.
+    fn tiling_target(&self, workspace: WorkspaceId) -> Rc<Node> {
+        let tiled = || {
+            self.mru_view
+                .iter()
+                .rev()
+                .filter_map(|id| self.view_nodes.get(id))
+                .filter(|node| {
+                    let n = node.n.borrow();
+                    let w = window(&n);
+                    w.workspace == workspace && w.in_tree()
+                })
+        };
+        tiled()
+            .find(|node| !window(&node.n.borrow()).private)
+            .or_else(|| tiled().next())
+            .cloned()
+            .unwrap_or_else(|| self.workspace(workspace).root.clone())
+    }
+
+    /// Most recently used window of the workspace that has a preselection
+    fn presel_target(&self, workspace: WorkspaceId) -> Option<Rc<Node>> {
+        self.mru_view
+            .iter()
+            .rev()
+            .filter_map(|id| self.view_nodes.get(id))
+            .find(|node| {
+                let n = node.n.borrow();
+                let w = window(&n);
+                w.workspace == workspace && w.presel.is_some()
+            })
+            .cloned()
+    }
+
+    fn take_out_of_tree(&mut self, node: &Rc<Node>, workspace: WorkspaceId) -> Result<()> {
+        window_mut(&mut node.n.borrow_mut()).presel = None;
+        self.workspace_mut(workspace).root =
+            tree::detach(node.clone())?.unwrap_or_else(tree::create_root);
+        Ok(())
+    }
+
+    fn put_in_tree(&mut self, node: &Rc<Node>, workspace: WorkspaceId) {
+        self.put_in_tree_at(node, workspace, None, None);
+    }
+
+    fn put_in_tree_at(
+        &mut self,
+        node: &Rc<Node>,
+        workspace: WorkspaceId,
+        direction: Option<Direction>,
+        ratio: Option<f32>,
+    ) {
+        // Preselection is used up by the window that goes there, unless the direction is given
+        let preselected = match direction {
+            Some(_) => None,
+            None => self.presel_target(workspace).map(|target| {
+                let presel = window_mut(&mut target.n.borrow_mut()).presel.take();
+                (target, presel.expect("BUG: preselection missing"))
+            }),
+        };
+        let (target, presel) = match (direction, preselected) {
+            (_, Some((target, presel))) => (
+                target,
+                tree::Presel {
+                    ratio: ratio.unwrap_or(presel.ratio),
+                    ..presel
+                },
+            ),
+            (Some(direction), None) => (
+                self.tiling_target(workspace),
+                tree::Presel::towards(direction, ratio.unwrap_or(0.5)),
+            ),
+            (None, None) => {
+                let target = self.tiling_target(workspace);
+                let presel = tree::Presel {
+                    ratio: ratio.unwrap_or(0.5),
+                    ..split_dir(target.clone()).into()
+                };
+                (target, presel)
+            }
+        };
+        self.workspace_mut(workspace).root = tree::insert(target, node.clone(), presel).root();
     }
 
     pub fn remove_node(&mut self, id: NodeId) -> Result<()> {
-        let Some((workspace, node)) =
-            self.view_nodes.get(&id).map(|n| (0, n)) // TODO workspace
+        let Some(node) = self.view_nodes.get(&id).cloned()
             else { return Err(format!("No window for {}", id)); };
 
-        self.workspaces[workspace].root = tree::remove_from_tree(node.clone())?;
+        let (workspace, in_tree, mapped) = {
+            let n = node.n.borrow();
+            let w = window(&n);
+            (w.workspace, w.in_tree(), w.mapped)
+        };
+
+        if !mapped {
+            self.view_nodes.remove(&id);
+            println!("Remove unmapped {}", id);
+            return Ok(());
+        }
+
+        for child in self.children_of(id) {
+            window_mut(&mut self.view_nodes[&child].n.borrow_mut()).parent = None;
+        }
+
+        if in_tree {
+            self.workspace_mut(workspace).root = tree::remove_from_tree(node)?;
+        }
 
         self.view_nodes.remove(&id);
         println!("Remove {}", id);
@@ -151,51 +822,651 @@ impl WindowManager {
         Ok(())
     }
 
+    /// Window that gets the space of this one when it's removed: the most recently used one of
+    /// its sibling in the tree. None for windows that aren't in the tree.
+    pub fn replacement_of(&self, id: NodeId) -> Option<NodeId> {
+        let node = self.node(id).ok()?;
+        if !window(&node.n.borrow()).in_tree() {
+            return None;
+        }
+        let sibling = tree::sibling_of(&node)?;
+        let ids: Vec<NodeId> = sibling.self_and_descendants().map(|x| x.id).collect();
+        self.mru_view.iter().rev().copied().find(|x| ids.contains(x))
+    }
+
+    /// Swap the tiles of two windows of the same workspace
+    pub fn swap(&mut self, id1: NodeId, id2: NodeId) -> Result<()> {
+        let node1 = self.node(id1)?;
+        let node2 = self.node(id2)?;
+        if id1 == id2 {
+            return Ok(());
+        }
+        {
+            let (n1, n2) = (node1.n.borrow(), node2.n.borrow());
+            let (w1, w2) = (window(&n1), window(&n2));
+            if !w1.in_tree() || !w2.in_tree() {
+                return Err("Only windows in the tree can be swapped".to_string());
+            }
+            if w1.workspace != w2.workspace {
+                return Err("Windows of different desktops can't be swapped".to_string());
+            }
+        }
+        tree::swap(node1, node2);
+        self.configure_views();
+        Ok(())
+    }
+
+    /// Move window to another workspace. Its children come along.
+    pub fn move_to_workspace(&mut self, id: NodeId, workspace: WorkspaceId) -> Result<()> {
+        self.check_workspace(workspace)?;
+        let node = self.node(id)?;
+        let (old_workspace, in_tree) = {
+            let n = node.n.borrow();
+            let w = window(&n);
+            (w.workspace, w.in_tree())
+        };
+        if old_workspace == workspace {
+            return Ok(());
+        }
+        println!("Move {} to workspace {}", id, workspace);
+
+        if in_tree {
+            self.take_out_of_tree(&node, old_workspace)?;
+            self.put_in_tree(&node, workspace);
+        }
+
+        let from = self.workspace(old_workspace).rect.clone();
+        let to = self.workspace(workspace).rect.clone();
+        {
+            let mut n = node.n.borrow_mut();
+            let w = window_mut(&mut n);
+            w.workspace = workspace;
+            if let Some(r) = &mut w.floating_rect {
+                r.x += to.x - from.x;
+                r.y += to.y - from.y;
+            }
+        }
+
+        for child in self.children_of(id) {
+            self.move_to_workspace(child, workspace)?;
+        }
+
+        self.configure_views();
+        Ok(())
+    }
+
+    pub fn set_state(&mut self, id: NodeId, state: WindowState) -> Result<()> {
+        let node = self.node(id)?;
+        let (old_state, workspace, hidden) = {
+            let n = node.n.borrow();
+            let w = window(&n);
+            (w.state, w.workspace, w.hidden)
+        };
+        if old_state == state {
+            return Ok(());
+        }
+        println!("State of {}: {:?} -> {:?}", id, old_state, state);
+
+        if state == WindowState::Floating || state == WindowState::PseudoTiled {
+            self.init_floating_rect(&node);
+        }
+
+        // Hidden window goes to the tree when it's shown again
+        if !hidden {
+            if old_state.in_tree() && !state.in_tree() {
+                self.take_out_of_tree(&node, workspace)?;
+            } else if !old_state.in_tree() && state.in_tree() {
+                self.put_in_tree(&node, workspace);
+            }
+        }
+
+        {
+            let mut n = node.n.borrow_mut();
+            let w = window_mut(&mut n);
+            w.last_state = old_state;
+            w.state = state;
+        }
+
+        self.configure_views();
+        Ok(())
+    }
+
+    /// Set the state, or if it's already set, go back to the previous one
+    pub fn toggle_state(&mut self, id: NodeId, state: WindowState) -> Result<()> {
+        if self.state(id)? != state {
+            self.set_state(id, state)
+        } else {
+            self.unset_state(id, state)
+        }
+    }
+
+    /// If window is in given state, go back to the previous one
+    pub fn unset_state(&mut self, id: NodeId, state: WindowState) -> Result<()> {
+        let (current, last) = {
+            let node = self.node(id)?;
+            let n = node.n.borrow();
+            let w = window(&n);
+            (w.state, w.last_state)
+        };
+
+        if current != state {
+            Ok(())
+        } else if last != state {
+            self.set_state(id, last)
+        } else {
+            self.set_state(id, WindowState::Tiled)
+        }
+    }
+
+    pub fn state(&self, id: NodeId) -> Result<WindowState> {
+        let node = self.node(id)?;
+        let n = node.n.borrow();
+        Ok(window(&n).state)
+    }
+
+    pub fn flag(&self, id: NodeId, flag: Flag) -> Result<bool> {
+        let node = self.node(id)?;
+        let n = node.n.borrow();
+        let w = window(&n);
+        Ok(match flag {
+            Flag::Hidden => w.hidden,
+            Flag::Locked => w.locked,
+            Flag::Sticky => w.sticky,
+            Flag::Private => w.private,
+            Flag::Marked => w.marked,
+            Flag::Urgent => w.urgent,
+        })
+    }
+
+    /// Marked windows, most recently used last
+    pub fn marked(&self) -> Vec<NodeId> {
+        self.mru_view
+            .iter()
+            .copied()
+            .filter(|id| window(&self.view_nodes[id].n.borrow()).marked)
+            .collect()
+    }
+
+    /// Set the flag on or off, or None to toggle it
+    pub fn set_flag(&mut self, id: NodeId, flag: Flag, value: Option<bool>) -> Result<()> {
+        let value = value.unwrap_or(!self.flag(id, flag)?);
+        println!("Flag {:?} of {}: {}", flag, id, value);
+        if flag == Flag::Hidden {
+            return self.set_hidden(id, value);
+        }
+        let node = self.node(id)?;
+        let mut n = node.n.borrow_mut();
+        let w = window_mut(&mut n);
+        match flag {
+            Flag::Hidden => (),
+            Flag::Locked => w.locked = value,
+            Flag::Sticky => w.sticky = value,
+            Flag::Private => w.private = value,
+            Flag::Marked => w.marked = value,
+            Flag::Urgent => w.urgent = value,
+        }
+        Ok(())
+    }
+
+    /// Hidden window leaves the tree and its sibling takes the space, but the view stays
+    fn set_hidden(&mut self, id: NodeId, hidden: bool) -> Result<()> {
+        let node = self.node(id)?;
+        let (workspace, state) = {
+            let n = node.n.borrow();
+            let w = window(&n);
+            if w.hidden == hidden {
+                return Ok(());
+            }
+            (w.workspace, w.state)
+        };
+
+        // Flag changes last, so the window isn't a tiling target for itself
+        if state.in_tree() && hidden {
+            self.take_out_of_tree(&node, workspace)?;
+        } else if state.in_tree() {
+            self.put_in_tree(&node, workspace);
+        }
+        window_mut(&mut node.n.borrow_mut()).hidden = hidden;
+
+        self.configure_views();
+        Ok(())
+    }
+
+    /// Put the window to the scratchpad, where it's floating and hidden until toggled, or take it
+    /// out and show it
+    pub fn set_scratchpad(&mut self, id: NodeId, scratchpad: bool) -> Result<()> {
+        let node = self.node(id)?;
+        println!("Scratchpad {}: {}", id, scratchpad);
+        window_mut(&mut node.n.borrow_mut()).scratchpad = scratchpad;
+        if scratchpad {
+            self.set_state(id, WindowState::Floating)?;
+        }
+        self.set_hidden(id, scratchpad)
+    }
+
+    /// Show the most recently used scratchpad window floating in the middle of the focused
+    /// workspace, or hide it if it's there already. Returns the window if it was shown.
+    pub fn toggle_scratchpad(&mut self) -> Result<Option<NodeId>> {
+        let id = self
+            .mru_view
+            .iter()
+            .rev()
+            .copied()
+            .find(|id| window(&self.view_nodes[id].n.borrow()).scratchpad)
+            .ok_or_else(|| "Scratchpad is empty".to_string())?;
+        let workspace = self.focused_workspace;
+
+        if !self.is_hidden(id) && self.workspace_of(id) == workspace {
+            self.set_hidden(id, true)?;
+            return Ok(None);
+        }
+
+        self.move_to_workspace(id, workspace)?;
+        let area = self.tiling_area(self.workspace(workspace));
+        {
+            let node = self.node(id)?;
+            let mut n = node.n.borrow_mut();
+            let w = window_mut(&mut n);
+            if let Some(rect) = &w.floating_rect {
+                w.floating_rect = Some(centered(&area, rect));
+            }
+        }
+        self.set_hidden(id, false)?;
+        Ok(Some(id))
+    }
+
+    /// Move the edges of the handle by dx and dy. Tiled windows change the split ratios, floating
+    /// ones their rect.
+    pub fn resize(&mut self, id: NodeId, handle: ResizeHandle, dx: f32, dy: f32) -> Result<()> {
+        let node = self.node(id)?;
+        let state = window(&node.n.borrow()).state;
+        println!("Resize {} {:?} by {} {}", id, handle, dx, dy);
+
+        let sides = [(handle.x, dx), (handle.y, dy)];
+        match state {
+            WindowState::Fullscreen => return Err("Can't resize fullscreen window".to_string()),
+            WindowState::Floating => {
+                let mut n = node.n.borrow_mut();
+                let w = window_mut(&mut n);
+                let Some(r) = &mut w.floating_rect else { return Ok(()); };
+                for (side, d) in sides {
+                    match side {
+                        Some(Direction::Left) => {
+                            let d = d.min(r.w - 1.0);
+                            r.x += d;
+                            r.w -= d;
+                        }
+                        Some(Direction::Right) => r.w = (r.w + d).max(1.0),
+                        Some(Direction::Up) => {
+                            let d = d.min(r.h - 1.0);
+                            r.y += d;
+                            r.h -= d;
+                        }
+                        Some(Direction::Down) => r.h = (r.h + d).max(1.0),
+                        None => {}
+                    }
+                }
+            }
+            WindowState::Tiled | WindowState::PseudoTiled => {
+                for (side, d) in sides {
+                    if let Some(side) = side {
+                        tree::move_edge(&node, side, d);
+                    }
+                }
+            }
+        }
+
+        self.configure_views();
+        Ok(())
+    }
+
+    pub fn set_layer(&mut self, id: NodeId, layer: Layer) -> Result<()> {
+        let node = self.node(id)?;
+        println!("Layer of {}: {:?}", id, layer);
+        window_mut(&mut node.n.borrow_mut()).layer = layer;
+        Ok(())
+    }
+
+    pub fn set_maximized(&mut self, id: NodeId, maximized: bool) -> Result<()> {
+        {
+            let node = self.node(id)?;
+            let mut n = node.n.borrow_mut();
+            window_mut(&mut n).maximized = maximized;
+        }
+        self.configure_views();
+        Ok(())
+    }
+
+    fn init_floating_rect(&self, node: &Rc<Node>) {
+        let mut n = node.n.borrow_mut();
+        let w = window_mut(&mut n);
+        if w.floating_rect.is_none() {
+            // Dialogs go on top of their parent
+            let area = match w.parent.and_then(|p| self.find_view(p)) {
+                Some(parent) => parent.content_and_rect().1.clone(),
+                None => self.workspace(w.workspace).rect.clone(),
+            };
+            let (width, height) = w
+                .view
+                .preferred_size()
+                .unwrap_or((area.w / 2.0, area.h / 2.0));
+            w.floating_rect = Some(centered(
+                &area,
+                &Rect {
+                    w: width,
+                    h: height,
+                    ..Rect::default()
+                },
+            ));
+        }
+    }
+
+    /// Preselect the side of the window where the next tiled window goes, or cancel with None
+    pub fn set_presel(&mut self, id: NodeId, direction: Option<Direction>) -> Result<()> {
+        let node = self.node(id)?;
+        let mut n = node.n.borrow_mut();
+        let w = window_mut(&mut n);
+        if direction.is_some() && !w.in_tree() {
+            return Err("Only tiled windows can be preselected".to_string());
+        }
+        // Preselected area keeps its size when the direction changes
+        let share = w.presel.map_or(0.5, |p| p.new_share());
+        w.presel = direction.map(|d| {
+            let mut presel = tree::Presel::towards(d, share);
+            presel.set_new_share(share);
+            presel
+        });
+        Ok(())
+    }
+
+    /// How much of the tile the preselected area takes
+    pub fn set_presel_ratio(&mut self, id: NodeId, ratio: f32) -> Result<()> {
+        let node = self.node(id)?;
+        let mut n = node.n.borrow_mut();
+        match &mut window_mut(&mut n).presel {
+            Some(presel) => {
+                presel.set_new_share(ratio);
+                Ok(())
+            }
+            None => Err("Window isn't preselected".to_string()),
+        }
+    }
+
+    /// Area of the tile that the next window would get, for presel feedback
+    pub fn presel_rect(&self, id: NodeId) -> Option<Rect> {
+        let node = self.view_nodes.get(&id)?;
+        let n = node.n.borrow();
+        let w = window(&n);
+        // Tile is covered by the fullscreen window
+        if w.state == WindowState::Fullscreen {
+            return None;
+        }
+        let presel = w.presel?;
+        let r = node.rect.borrow().clone();
+        let share = presel.new_share();
+        let area = match (presel.dir, presel.first) {
+            (tree::SplitDir::V, true) => Rect {
+                w: r.w * share,
+                ..r
+            },
+            (tree::SplitDir::V, false) => Rect {
+                x: r.x + r.w - r.w * share,
+                w: r.w * share,
+                ..r
+            },
+            (tree::SplitDir::H, true) => Rect {
+                h: r.h * share,
+                ..r
+            },
+            (tree::SplitDir::H, false) => Rect {
+                y: r.y + r.h - r.h * share,
+                h: r.h * share,
+                ..r
+            },
+        };
+        // Gap is around the tile, like it is around the window
+        Some(shrink(&area, (self.layout.window_gap / 2.0).max(0.0)))
+    }
+
+    /// Mapped window
+    fn node(&self, id: NodeId) -> Result<Rc<Node>> {
+        self.view_nodes
+            .get(&id)
+            .filter(|node| window(&node.n.borrow()).mapped)
+            .cloned()
+            .ok_or_else(|| format!("No mapped window for {}", id))
+    }
+
+    pub fn set_layout(&mut self, layout: Layout) {
+        if self.layout != layout {
+            self.layout = layout;
+            self.configure_views();
+        }
+    }
+
+    /// Padding of the output
+    pub fn output_padding(&mut self, id: OutputId) -> Result<&mut Padding> {
+        self.outputs
+            .iter_mut()
+            .find(|o| o.id == id)
+            .map(|o| &mut o.padding)
+            .ok_or_else(|| format!("No output {}", id))
+    }
+
+    /// Padding of the workspace, in addition to the one of its output
+    pub fn workspace_padding(&mut self, id: WorkspaceId) -> Result<&mut Padding> {
+        self.check_workspace(id)?;
+        Ok(&mut self.workspace_mut(id).padding)
+    }
+
+    /// Where tiled and maximized windows go, the workspace without padding
+    fn tiling_area(&self, ws: &Workspace) -> Rect {
+        let output_padding = self
+            .outputs
+            .iter()
+            .find(|o| Some(o.id) == ws.output_id)
+            .map(|o| o.padding)
+            .unwrap_or_default();
+        let p = self.layout.padding.add(output_padding).add(ws.padding);
+        Rect {
+            x: ws.rect.x + p.left,
+            y: ws.rect.y + p.top,
+            w: (ws.rect.w - p.left - p.right).max(1.0),
+            h: (ws.rect.h - p.top - p.bottom).max(1.0),
+        }
+    }
+
     pub fn configure_views(&mut self) {
         println!("Start configure");
-        // TODO handle workspaces
-        let ws = &mut self.workspaces[0];
-        let rect = ws.rect.clone();
-        configure_views(ws.root.clone(), rect);
+        for ws in self.workspaces.iter() {
+            let monocle = self.is_monocle(ws);
+            let gap = if monocle && self.layout.gapless_monocle {
+                0.0
+            } else {
+                self.layout.window_gap / 2.0
+            };
+            let tiles = Tiles {
+                border_width: if monocle && self.layout.borderless_monocle {
+                    0.0
+                } else {
+                    self.layout.border_width
+                },
+                gap,
+                monocle,
+            };
+            // Half of the gap around each tile, and around the area, makes it the same everywhere
+            let area = shrink(&self.tiling_area(ws), gap);
+            configure_views(ws.root.clone(), area, &ws.rect, tiles);
+        }
+        for node in self.view_nodes.values() {
+            let mut n = node.n.borrow_mut();
+            let w = window_mut(&mut n);
+            if w.mapped && w.state == WindowState::Floating {
+                // Floating rect is the size of the view, border goes around it
+                let rect = if w.maximized {
+                    let area = self.tiling_area(self.workspace(w.workspace));
+                    shrink(&area, self.layout.border_width)
+                } else {
+                    w.floating_rect.clone().unwrap_or_default()
+                };
+                w.border_width = self.layout.border_width;
+                w.view.as_mut().configure_rect(&rect, w.state, w.maximized);
+            }
+        }
         println!("End configure");
     }
 
     pub fn update_outputs(&mut self, outputs: impl Iterator<Item = OutputInfo>) {
-        // Just one workspace per output for now, to get started
-        let mut old_workspaces = Vec::new();
-        std::mem::swap(&mut old_workspaces, &mut self.workspaces);
-        let mut old_workspaces: HashMap<Option<OutputId>, Workspace> = old_workspaces
-            .into_iter()
-            .map(|x| (x.output_id, x))
-            .collect();
+        let outputs: Vec<OutputInfo> = outputs.collect();
+
+        self.outputs.retain(|o| outputs.iter().any(|x| x.id == o.id));
+        for ws in self.workspaces.iter_mut() {
+            if !outputs.iter().any(|o| Some(o.id) == ws.output_id) {
+                ws.output_id = None;
+            }
+        }
 
-        self.workspaces = outputs
-            .map(|o| {
-                old_workspaces
-                    .remove(&Some(o.id))
-                    .map(|w| Workspace { rect: o.rect.clone(), ..w })
-                    .unwrap_or_else(|| Workspace {
-                        root: tree::create_root(),
-                        rect: o.rect,
-                        output_id: Some(o.id),
-                    })
+        for o in outputs {
+            if let Some(existing) = self.outputs.iter_mut().find(|x| x.id == o.id) {
+                existing.rect = o.rect;
+                continue;
+            }
+            // New output gets the workspaces that have no output, or a new one
+            let orphans: Vec<WorkspaceId> = self
+                .workspaces
+                .iter()
+                .filter(|w| w.output_id.is_none())
+                .map(|w| w.id)
+                .collect();
+            let active_workspace = match orphans.first() {
+                Some(first) => {
+                    for id in orphans.iter() {
+                        self.workspace_mut(*id).output_id = Some(o.id);
+                    }
+                    *first
+                }
+                None => self.create_workspace(None, Some(o.id), o.rect.clone()),
+            };
+            self.outputs.push(OutputState {
+                id: o.id,
+                rect: o.rect,
+                active_workspace,
+                padding: Padding::default(),
+            });
+        }
+
+        // Workspaces of removed outputs go to some other output
+        if let Some(first) = self.outputs.first() {
+            let first = first.id;
+            for ws in self.workspaces.iter_mut() {
+                if ws.output_id.is_none() {
+                    ws.output_id = Some(first);
+                }
+            }
+        }
+
+        let rects: Vec<(WorkspaceId, Rect)> = self
+            .workspaces
+            .iter()
+            .filter_map(|ws| {
+                let output = self.outputs.iter().find(|o| Some(o.id) == ws.output_id)?;
+                Some((ws.id, output.rect.clone()))
             })
             .collect();
+        for (id, rect) in rects {
+            self.set_workspace_rect(id, rect);
+        }
+
+        if !self.is_visible(self.focused_workspace) {
+            if let Some(output) = self.outputs.first() {
+                self.focused_workspace = output.active_workspace;
+            }
+        }
+
+        self.configure_views();
+    }
+
+    /// Move workspace to new area. Floating windows move with it.
+    fn set_workspace_rect(&mut self, id: WorkspaceId, rect: Rect) {
+        let old = std::mem::replace(&mut self.workspace_mut(id).rect, rect.clone());
+        for node in self.view_nodes.values() {
+            let mut n = node.n.borrow_mut();
+            let w = window_mut(&mut n);
+            if w.workspace == id {
+                if let Some(r) = &mut w.floating_rect {
+                    r.x += rect.x - old.x;
+                    r.y += rect.y - old.y;
+                }
+            }
+        }
+    }
+
+    /// Rename workspaces of the output, creating or removing some to get the right amount. Windows
+    /// of removed workspaces go to the first one.
+    pub fn set_workspace_names(&mut self, output: Option<OutputId>, names: Vec<String>) -> Result<()> {
+        if names.is_empty() {
+            return Err("Output needs at least one workspace".to_string());
+        }
+        let names_len = names.len();
+        let existing: Vec<WorkspaceId> = self
+            .workspaces
+            .iter()
+            .filter(|w| w.output_id == output)
+            .map(|w| w.id)
+            .collect();
+        let rect = match existing.first() {
+            Some(id) => self.workspace(*id).rect.clone(),
+            None => return Err("No such output".to_string()),
+        };
+
+        for (i, name) in names.into_iter().enumerate() {
+            match existing.get(i) {
+                Some(id) => self.workspace_mut(*id).name = name,
+                None => {
+                    self.create_workspace(Some(name), output, rect.clone());
+                }
+            }
+        }
+
+        let first = existing[0];
+        let removed: Vec<WorkspaceId> = existing.into_iter().skip(names_len).collect();
+        for ws in removed.iter() {
+            let windows: Vec<NodeId> = self
+                .mru_view
+                .iter()
+                .copied()
+                .filter(|id| self.workspace_of(*id) == *ws)
+                .collect();
+            for id in windows {
+                self.move_to_workspace(id, first)?;
+            }
+            for o in self.outputs.iter_mut() {
+                if o.active_workspace == *ws {
+                    o.active_workspace = first;
+                }
+            }
+            if self.focused_workspace == *ws {
+                self.focused_workspace = first;
+            }
+        }
+        self.workspaces.retain(|w| !removed.contains(&w.id));
+
         self.configure_views();
+        Ok(())
     }
 
     pub fn neighbor(&self, direction: Direction) -> Option<Rc<Node>> {
+        // Node rects are the tiles before gaps and borders, so neighbors share an edge. Tiles
+        // that only touch at a corner don't count.
         fn overlaps(a1: f32, l1: f32, a2: f32, l2: f32) -> bool {
-            // TODO gaps?
-            dbg!(a1, a2, l1, l2);
-            a1 + l1 >= a2 && a2 + l2 >= a1 
+            a1 + l1 > a2 && a2 + l2 > a1
         }
 
         let active = self.active_node()?;
         let active_rect = active.rect.borrow();
         let potential_neighbors = tree::nodes_to_direction(&active, direction);
 
-        let n = self.mru_view.iter().rev()
+        self.mru_view.iter().rev()
             .filter_map(|nodeid| potential_neighbors.get(nodeid))
             .find(|node| {
                 let r = node.rect.borrow();
@@ -205,9 +1476,7 @@ impl WindowManager {
                     overlaps(r.y, r.h, active_rect.y, active_rect.h)
                 }
             })
-            .cloned();
-        dbg!(n.is_some());
-        n
+            .cloned()
     }
 }
 
@@ -219,51 +1488,312 @@ fn split_dir(node: Rc<Node>) -> tree::SplitDir {
     }
 }
 
-fn configure_views(root: Rc<Node>, rect: Rect) {
+/// Put rect of given size to the center of area, shrinking it to fit if needed
+fn centered(area: &Rect, size: &Rect) -> Rect {
+    let w = size.w.min(area.w);
+    let h = size.h.min(area.h);
+    Rect {
+        x: area.x + (area.w - w) / 2.0,
+        y: area.y + (area.h - h) / 2.0,
+        w,
+        h,
+    }
+}
+
+/// Rect made smaller by the amount on every side
+fn shrink(rect: &Rect, by: f32) -> Rect {
+    Rect {
+        x: rect.x + by,
+        y: rect.y + by,
+        w: (rect.w - 2.0 * by).max(1.0),
+        h: (rect.h - 2.0 * by).max(1.0),
+    }
+}
+
+/// How tiles of a workspace are made
+#[derive(Clone, Copy)]
+struct Tiles {
+    border_width: f32,
+    /// Around every tile
+    gap: f32,
+    /// Every tile gets the whole rect
+    monocle: bool,
+}
+
+/// Lay out the tree in the rect. Every tile is shrunk by the gap and the border.
+fn configure_views(root: Rc<Node>, rect: Rect, workspace_rect: &Rect, tiles: Tiles) {
     use tree::SplitDir;
 
     *root.rect.borrow_mut() = rect.clone();
 
     match &mut *root.n.borrow_mut() {
         tree::N::Placeholder => {}
+        tree::N::Split(s) if tiles.monocle => {
+            configure_views(s.a.clone(), rect.clone(), workspace_rect, tiles);
+            configure_views(s.b.clone(), rect, workspace_rect, tiles);
+        }
         tree::N::Split(s) => match s.dir {
             SplitDir::H => {
-                configure_views(
-                    s.a.clone(),
-                    Rect {
-                        h: rect.h / 2.0,
-                        ..rect
-                    },
-                );
+                let h = rect.h * s.ratio;
+                configure_views(s.a.clone(), Rect { h, ..rect }, workspace_rect, tiles);
                 configure_views(
                     s.b.clone(),
                     Rect {
-                        h: rect.h / 2.0,
-                        y: rect.y + rect.h / 2.0,
+                        h: rect.h - h,
+                        y: rect.y + h,
                         ..rect
                     },
+                    workspace_rect,
+                    tiles,
                 );
             }
             SplitDir::V => {
-                configure_views(
-                    s.a.clone(),
-                    Rect {
-                        w: rect.w / 2.0,
-                        ..rect
-                    },
-                );
+                let w = rect.w * s.ratio;
+                configure_views(s.a.clone(), Rect { w, ..rect }, workspace_rect, tiles);
                 configure_views(
                     s.b.clone(),
                     Rect {
-                        w: rect.w / 2.0,
-                        x: rect.x + rect.w / 2.0,
+                        w: rect.w - w,
+                        x: rect.x + w,
                         ..rect
                     },
+                    workspace_rect,
+                    tiles,
                 );
             }
         },
         tree::N::Leaf(leaf) => {
-            leaf.content.view.as_mut().configure_rect(&rect);
+            let window = &mut leaf.content;
+            window.border_width = match window.state {
+                WindowState::Fullscreen => 0.0,
+                _ => tiles.border_width,
+            };
+            let rect = shrink(&rect, tiles.gap + window.border_width);
+            let view_rect = match window.state {
+                WindowState::Tiled | WindowState::Floating => rect,
+                WindowState::PseudoTiled => match &window.floating_rect {
+                    Some(size) => centered(&rect, size),
+                    None => rect,
+                },
+                WindowState::Fullscreen => workspace_rect.clone(),
+            };
+            window
+                .view
+                .as_mut()
+                .configure_rect(&view_rect, window.state, window.maximized);
         }
     }
 }
+
+#[cfg(test)]
+fn output(id: OutputId, x: f32) -> OutputInfo {
+    OutputInfo {
+        id,
+        rect: Rect {
+            x,
+            y: 0.0,
+            w: 1000.0,
+            h: 500.0,
+        },
+    }
+}
+
+/// Mapped window
+#[cfg(test)]
+fn add(wm: &mut WindowManager, placement: Placement) -> NodeId {
+    let id = wm.add_view(View::empty);
+    wm.map_view(id, placement).unwrap();
+    id
+}
+
+/// Views of the output, bottom first
+#[cfg(test)]
+fn rendered(wm: &WindowManager, output: OutputId) -> Vec<NodeId> {
+    wm.views_for_render(output)
+        .map(|v| v.content_and_rect().0.id)
+        .collect()
+}
+
+#[test]
+fn test_focus_workspace() {
+    let mut wm = WindowManager::new();
+    wm.update_outputs(vec![output(1, 0.0), output(2, 1000.0)].into_iter());
+    assert_eq!(2, wm.workspaces().count());
+    wm.set_workspace_names(Some(1), vec!["a".to_string(), "b".to_string()])
+        .unwrap();
+    let b = wm.workspaces().find(|w| w.name == "b").unwrap().id;
+    assert!(!wm.is_visible(b));
+
+    wm.focus_workspace(b).unwrap();
+    assert_eq!(b, wm.focused_workspace());
+    assert_eq!(Some(1), wm.focused_output());
+    assert!(wm.is_visible(b));
+    assert_eq!(2, wm.workspaces().filter(|w| wm.is_visible(w.id)).count());
+    assert!(wm.focus_workspace(42).is_err());
+}
+
+#[test]
+fn test_move_to_workspace() {
+    let mut wm = WindowManager::new();
+    wm.update_outputs(vec![output(1, 0.0), output(2, 1000.0)].into_iter());
+    let first = wm.focused_workspace();
+    let second = wm.workspaces().find(|w| w.id != first).unwrap().id;
+    let a = add(&mut wm, Placement::default());
+    let b = add(&mut wm, Placement::default());
+    assert_eq!(2, wm.views_for_render(1).count());
+
+    wm.move_to_workspace(a, second).unwrap();
+    assert_eq!(second, wm.workspace_of(a));
+    assert_eq!(vec![b], rendered(&wm, 1));
+    assert_eq!(vec![a], rendered(&wm, 2));
+    // Both are alone in their tree now, so they take the whole output
+    let rect = wm.find_view(a).unwrap().content_and_rect().1.clone();
+    assert_eq!((1000.0, 1000.0), (rect.x, rect.w));
+    let rect = wm.find_view(b).unwrap().content_and_rect().1.clone();
+    assert_eq!((0.0, 1000.0), (rect.x, rect.w));
+
+    wm.set_state(b, WindowState::Floating).unwrap();
+    let before = wm.find_view(b).unwrap().content_and_rect().1.clone();
+    wm.move_to_workspace(b, second).unwrap();
+    let after = wm.find_view(b).unwrap().content_and_rect().1.clone();
+    assert_eq!(before.x + 1000.0, after.x);
+    assert_eq!(0, wm.views_for_render(1).count());
+    assert!(wm.move_to_workspace(a, 42).is_err());
+}
+
+#[test]
+fn test_dialog_follows_parent() {
+    let mut wm = WindowManager::new();
+    wm.update_outputs(vec![output(1, 0.0), output(2, 1000.0)].into_iter());
+    let second = wm.workspaces().find(|w| w.output_id == Some(2)).unwrap().id;
+    let parent = add(&mut wm, Placement::default());
+    let other = add(&mut wm, Placement::default());
+    let dialog = add(
+        &mut wm,
+        Placement {
+            parent: Some(parent),
+            state: WindowState::Floating,
+            ..Placement::default()
+        },
+    );
+    assert_eq!(WindowState::Floating, wm.state(dialog).unwrap());
+    // Dialog stays above its parent, even when the parent is used more recently
+    wm.touch_node(parent);
+    assert_eq!(vec![other, parent, dialog], rendered(&wm, 1));
+
+    wm.move_to_workspace(parent, second).unwrap();
+    assert_eq!(second, wm.workspace_of(dialog));
+    assert_eq!(vec![parent, dialog], rendered(&wm, 2));
+    assert_eq!(vec![other], rendered(&wm, 1));
+}
+
+#[test]
+fn test_presel() {
+    let mut wm = WindowManager::new();
+    wm.update_outputs(vec![output(1, 0.0)].into_iter());
+    let a = add(&mut wm, Placement::default());
+    let b = add(&mut wm, Placement::default());
+    assert!(wm.set_presel_ratio(a, 0.3).is_err());
+
+    // Preselection of a window that isn't the most recently used one is used too
+    wm.set_presel(a, Some(Direction::Left)).unwrap();
+    wm.set_presel_ratio(a, 0.3).unwrap();
+    // Preselected area keeps its share when it moves to the other side
+    wm.set_presel(a, Some(Direction::Right)).unwrap();
+    let ra = wm.find_view(a).unwrap().content_and_rect().1.clone();
+    let area = wm.presel_rect(a).unwrap();
+    assert_eq!((ra.x + ra.w * 0.7, ra.w * 0.3), (area.x, area.w.round()));
+
+    let c = add(&mut wm, Placement::default());
+    let ra = wm.find_view(a).unwrap().content_and_rect().1.clone();
+    let rc = wm.find_view(c).unwrap().content_and_rect().1.clone();
+    assert_eq!((ra.x + ra.w, ra.y), (rc.x, rc.y));
+    assert_eq!(150.0, rc.w.round());
+    assert!(wm.presel_rect(a).is_none());
+    assert_eq!(500.0, wm.find_view(b).unwrap().content_and_rect().1.w);
+
+    wm.set_presel(c, Some(Direction::Down)).unwrap();
+    wm.set_presel(c, None).unwrap();
+    assert!(wm.presel_rect(c).is_none());
+    wm.set_state(c, WindowState::Floating).unwrap();
+    assert!(wm.set_presel(c, Some(Direction::Up)).is_err());
+}
+
+#[test]
+fn test_swap() {
+    let mut wm = WindowManager::new();
+    wm.update_outputs(vec![output(1, 0.0), output(2, 1000.0)].into_iter());
+    let second = wm.workspaces().find(|w| w.output_id == Some(2)).unwrap().id;
+    let a = add(&mut wm, Placement::default());
+    let b = add(&mut wm, Placement::default());
+    let x = |wm: &WindowManager, id| wm.find_view(id).unwrap().content_and_rect().1.x;
+    assert!(x(&wm, a) < x(&wm, b));
+
+    wm.swap(a, a).unwrap();
+    assert!(x(&wm, a) < x(&wm, b));
+    wm.swap(a, b).unwrap();
+    assert!(x(&wm, b) < x(&wm, a));
+
+    let floating = add(
+        &mut wm,
+        Placement {
+            state: WindowState::Floating,
+            ..Placement::default()
+        },
+    );
+    assert!(wm.swap(a, floating).is_err());
+    let other = add(
+        &mut wm,
+        Placement {
+            workspace: Some(second),
+            ..Placement::default()
+        },
+    );
+    assert!(wm.swap(a, other).is_err());
+    assert_eq!(vec![other], rendered(&wm, 2));
+}
+
+#[test]
+fn test_stacking_order() {
+    let mut wm = WindowManager::new();
+    wm.update_outputs(vec![output(1, 0.0)].into_iter());
+    let floating = || Placement {
+        state: WindowState::Floating,
+        ..Placement::default()
+    };
+    let t1 = add(&mut wm, Placement::default());
+    let t2 = add(&mut wm, Placement::default());
+    let f = add(&mut wm, floating());
+    let d = add(
+        &mut wm,
+        Placement {
+            parent: Some(t1),
+            ..floating()
+        },
+    );
+    // Floating over tiled, and MRU between the floating ones
+    assert_eq!(vec![t1, t2, f, d], rendered(&wm, 1));
+    wm.touch_node(f);
+    assert_eq!(vec![t1, t2, d, f], rendered(&wm, 1));
+    wm.touch_node(t1);
+    assert_eq!(vec![t2, t1, f, d], rendered(&wm, 1));
+
+    // Layer goes before the rest, and the dialog keeps above its parent
+    wm.set_layer(t1, Layer::Above).unwrap();
+    assert_eq!(vec![t2, f, t1, d], rendered(&wm, 1));
+    wm.set_layer(t1, Layer::Normal).unwrap();
+    // Dialog can't go below its parent
+    wm.set_layer(d, Layer::Below).unwrap();
+    assert_eq!(vec![t2, t1, d, f], rendered(&wm, 1));
+    wm.set_layer(t2, Layer::Below).unwrap();
+    wm.set_layer(d, Layer::Above).unwrap();
+    assert_eq!(vec![t2, t1, f, d], rendered(&wm, 1));
+    wm.touch_node(f);
+    assert_eq!(vec![t2, t1, f, d], rendered(&wm, 1));
+
+    let found: Vec<NodeId> = wm
+        .views_for_finding()
+        .map(|v| v.content_and_rect().0.id)
+        .collect();
+    assert_eq!(vec![d, f, t1, t2], found);
+}
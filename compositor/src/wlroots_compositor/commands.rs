@@ -0,0 +1,395 @@
+use std::ffi::CString;
+use std::ptr;
+
+use wl_sys as wl;
+
+use crate::bindings::{Bindings, KeyCombo};
+use crate::command::{
+    self, BindCommand, Command, ConfigTarget, DesktopCommand, DesktopSelector, InputCommand,
+    MonitorCommand, MonitorSelector, NodeCommand, NodeSelector, Query, RuleCommand,
+};
+use crate::input_config::InputConfig;
+use crate::rules::Rules;
+use crate::settings::Settings;
+use crate::types::{NodeId, Result, WorkspaceId};
+use crate::window_manager::{Flag, Layout};
+
+use super::runner::{cursor_pos, find_window, handle_cursor_move, run_config};
+use super::server::{Server, ShellView};
+use super::wl_util::now_msec;
+
+impl Server {
+    pub fn run_command_line(&mut self, line: &str) -> Result<String> {
+        let command = command::parse_line(line)?;
+        self.execute_command(command)
+    }
+
+    /// Run the command, return what should be told to the one who asked
+    pub fn execute_command(&mut self, command: Command) -> Result<String> {
+        match command {
+            Command::Node(target, commands) => {
+                for id in self.select_nodes(target)? {
+                    for c in commands.iter() {
+                        self.execute_node_command(id, c.clone())?;
+                    }
+                }
+                Ok(String::new())
+            }
+            Command::Desktop(target, commands) => {
+                let workspace = self.select_desktop(&target)?;
+                for c in commands {
+                    match c {
+                        DesktopCommand::Focus(sel) => {
+                            let other = match sel {
+                                DesktopSelector::Focused => workspace,
+                                sel => self.select_desktop(&sel)?,
+                            };
+                            self.focus_workspace(other)?;
+                        }
+                        DesktopCommand::Layout(layout) => {
+                            self.wm.set_desktop_layout(workspace, layout)?;
+                            self.layout_changed();
+                        }
+                    }
+                }
+                Ok(String::new())
+            }
+            Command::Monitor(target, MonitorCommand::Desktops(names)) => {
+                let output = match target {
+                    MonitorSelector::Focused => self.wm.focused_output(),
+                    MonitorSelector::Id(id) => Some(id),
+                };
+                self.wm.set_workspace_names(output, names)?;
+                self.refocus();
+                self.layout_changed();
+                Ok(String::new())
+            }
+            Command::Config(name, None) => Ok(format!("{}\n", self.settings.get(&name)?)),
+            Command::Config(name, Some(value)) => {
+                self.settings.set(&name, &value)?;
+                self.apply_settings();
+                Ok(String::new())
+            }
+            Command::LocalConfig(target, name, value) => self.local_config(target, &name, value),
+            Command::Rule(RuleCommand::Add(rule)) => {
+                self.rules.add(rule);
+                Ok(String::new())
+            }
+            Command::Rule(RuleCommand::Remove(sel)) => {
+                self.rules.remove(&sel)?;
+                Ok(String::new())
+            }
+            Command::Rule(RuleCommand::List) => Ok(self.rules.list()),
+            Command::Bind(BindCommand::Add(mode, spec, command)) => {
+                let chord = KeyCombo::parse_chord(&spec, keysym_from_name)?;
+                self.bindings.bind(&mode, &spec, chord, command);
+                Ok(String::new())
+            }
+            Command::Bind(BindCommand::Remove(mode, spec)) => {
+                let chord = KeyCombo::parse_chord(&spec, keysym_from_name)?;
+                self.bindings.unbind(&mode, &chord)?;
+                Ok(String::new())
+            }
+            Command::Bind(BindCommand::List) => Ok(self.bindings.list()),
+            Command::Mode(None) => Ok(format!("{}\n", self.bindings.mode())),
+            Command::Mode(Some(mode)) => {
+                self.bindings.set_mode(&mode)?;
+                Ok(String::new())
+            }
+            Command::Input(InputCommand::Setting(device, name, None)) => Ok(format!(
+                "{}\n",
+                self.input_config.get(device.as_deref(), &name)?
+            )),
+            Command::Input(InputCommand::Setting(device, name, Some(value))) => {
+                let old = self.input_config.clone();
+                self.input_config.set(device.as_deref(), &name, &value)?;
+                if let Err(e) = self.configure_inputs() {
+                    self.input_config = old;
+                    let _ = self.configure_inputs();
+                    return Err(e);
+                }
+                Ok(String::new())
+            }
+            Command::Input(InputCommand::LayoutGroup(device, group)) => {
+                self.switch_layout_group(device.as_deref(), group)?;
+                Ok(String::new())
+            }
+            Command::Input(InputCommand::List) => Ok(self.list_inputs()),
+            Command::Reload => {
+                println!("Reload");
+                self.settings = Settings::default();
+                self.rules = Rules::default();
+                self.bindings = Bindings::default();
+                self.input_config = InputConfig::default();
+                let inputs = self.configure_inputs();
+                run_config(self);
+                inputs.map(|_| String::new())
+            }
+            Command::Query(query, sel) => {
+                let ids = match sel {
+                    Some(sel) => self.select_nodes(sel)?,
+                    None => self.wm.views().map(|v| v.content_and_rect().0.id).collect(),
+                };
+                Ok(ids
+                    .into_iter()
+                    .map(|id| match query {
+                        Query::Nodes => format!("{:#x}\n", id),
+                        Query::Tree => self.describe_node(id),
+                    })
+                    .collect())
+            }
+            Command::ToggleScratchpad => {
+                match self.wm.toggle_scratchpad()? {
+                    Some(id) => self.focus_node(id),
+                    None => self.refocus(),
+                }
+                self.layout_changed();
+                Ok(String::new())
+            }
+            Command::Spawn(command) => {
+                println!("Spawn {:?}", command);
+                let mut process = match command.as_slice() {
+                    [shell_command] => {
+                        let mut process = std::process::Command::new("sh");
+                        process.arg("-c").arg(shell_command);
+                        process
+                    }
+                    [program, args @ ..] => {
+                        let mut process = std::process::Command::new(program);
+                        process.args(args);
+                        process
+                    }
+                    [] => return Err("Nothing to spawn".to_string()),
+                };
+                process
+                    .env("WAYLAND_DISPLAY", &self.wayland_display_name)
+                    .spawn()
+                    .map_err(|e| format!("Failed to start {}: {}", command[0], e))?;
+                Ok(String::new())
+            }
+        }
+    }
+
+    fn execute_node_command(&mut self, id: NodeId, command: NodeCommand) -> Result<()> {
+        match command {
+            NodeCommand::Focus(sel) => {
+                let other = self.select_node(sel)?;
+                if self.wm.flag(other, Flag::Hidden)? {
+                    return Err("Node is hidden".to_string());
+                }
+                self.focus_node(other);
+                if self.settings.pointer_follows_focus {
+                    self.warp_pointer_to(other);
+                }
+            }
+            NodeCommand::Swap(sel) => {
+                let other = self.select_node(sel)?;
+                println!("swap {} {}", id, other);
+                self.wm.swap(id, other)?;
+            }
+            NodeCommand::State(state) => self.wm.set_state(id, state)?,
+            NodeCommand::ToggleState(state) => self.wm.toggle_state(id, state)?,
+            NodeCommand::Close if self.wm.flag(id, Flag::Locked)? => {
+                println!("{} is locked, not closing", id);
+            }
+            NodeCommand::Close => self.close_node(id)?,
+            NodeCommand::Layer(layer) => self.wm.set_layer(id, layer)?,
+            NodeCommand::Resize(handle, dx, dy) => {
+                self.wm.resize(id, handle, dx as f32, dy as f32)?
+            }
+            NodeCommand::Presel(direction) => self.wm.set_presel(id, direction)?,
+            NodeCommand::PreselRatio(ratio) => self.wm.set_presel_ratio(id, ratio)?,
+            NodeCommand::Scratchpad(scratchpad) => {
+                self.wm.set_scratchpad(id, scratchpad)?;
+                self.refocus();
+            }
+            NodeCommand::Flag(flag, value) => {
+                self.wm.set_flag(id, flag, value)?;
+                // Hidden window can't keep the focus
+                self.refocus();
+            }
+            NodeCommand::ToDesktop(sel, follow) => {
+                let workspace = self.select_desktop(&sel)?;
+                self.wm.move_to_workspace(id, workspace)?;
+                if follow {
+                    self.wm.focus_workspace(workspace)?;
+                    self.focus_node(id);
+                } else {
+                    self.refocus();
+                }
+            }
+        }
+        self.layout_changed();
+        Ok(())
+    }
+
+    fn select_node(&self, sel: NodeSelector) -> Result<NodeId> {
+        match sel {
+            NodeSelector::Focused => self.wm.active_node().map(|n| n.id),
+            NodeSelector::Pointed => find_window(self, cursor_pos(self))
+                .map(|(viewref, _, _)| viewref.content_and_rect().0.id),
+            NodeSelector::Id(id) => self.wm.find_view(id).map(|_| id),
+            NodeSelector::Direction(dir) => self.wm.neighbor(dir).map(|n| n.id),
+            NodeSelector::Marked => self.wm.marked().last().copied(),
+        }
+        .ok_or_else(|| "No such node".to_string())
+    }
+
+    /// Nodes that node commands are run for, many if they are marked
+    fn select_nodes(&self, sel: NodeSelector) -> Result<Vec<NodeId>> {
+        match sel {
+            NodeSelector::Marked => match self.wm.marked() {
+                marked if marked.is_empty() => Err("No marked nodes".to_string()),
+                marked => Ok(marked),
+            },
+            sel => Ok(vec![self.select_node(sel)?]),
+        }
+    }
+
+    /// `0x2a desktop=II state=tiled layer=normal flags=marked,sticky`
+    fn describe_node(&self, id: NodeId) -> String {
+        let Some(viewref) = self.wm.find_view(id) else {
+            return String::new();
+        };
+        let w = viewref.window();
+        let desktop = self
+            .wm
+            .workspaces()
+            .find(|ws| ws.id == w.workspace)
+            .map_or("?", |ws| ws.name.as_str());
+        let flags: Vec<&str> = Flag::ALL
+            .iter()
+            .filter(|f| self.wm.flag(id, **f).unwrap_or(false))
+            .map(|f| f.name())
+            .collect();
+        format!(
+            "{:#x} desktop={} state={} layer={} flags={}\n",
+            id,
+            desktop,
+            command::state_name(w.state),
+            command::layer_name(w.layer),
+            if flags.is_empty() { "-".to_string() } else { flags.join(",") }
+        )
+    }
+
+    pub fn select_desktop(&self, sel: &DesktopSelector) -> Result<WorkspaceId> {
+        let ids: Vec<WorkspaceId> = self.wm.workspaces().map(|w| w.id).collect();
+        let focused = self.wm.focused_workspace();
+        let focused_idx = ids.iter().position(|id| *id == focused).unwrap_or(0);
+
+        match sel {
+            DesktopSelector::Focused => Some(focused),
+            DesktopSelector::Next => ids.get((focused_idx + 1) % ids.len()).copied(),
+            DesktopSelector::Prev => ids.get((focused_idx + ids.len() - 1) % ids.len()).copied(),
+            DesktopSelector::Index(i) => ids.get(i - 1).copied(),
+            DesktopSelector::Name(name) => self
+                .wm
+                .workspaces()
+                .find(|w| w.name == *name)
+                .map(|w| w.id),
+        }
+        .ok_or_else(|| "No such desktop".to_string())
+    }
+
+    fn close_node(&self, id: NodeId) -> Result<()> {
+        let viewref = self.wm.find_view(id).ok_or_else(|| "No such node".to_string())?;
+        match &viewref.content_and_rect().0.shell_surface {
+            ShellView::Xdg(xdg) => unsafe {
+                wl::wlr_xdg_toplevel_send_close(xdg.xdgsurface.xdg_surface);
+            },
+            ShellView::Empty => (),
+        }
+        Ok(())
+    }
+
+    pub fn focus_workspace(&mut self, workspace: WorkspaceId) -> Result<()> {
+        self.wm.focus_workspace(workspace)?;
+        self.refocus();
+        self.layout_changed();
+        Ok(())
+    }
+
+    /// Pass the settings that the window manager needs to it
+    pub fn apply_settings(&mut self) {
+        let s = &self.settings;
+        self.wm.set_layout(Layout {
+            border_width: s.border_width as f32,
+            window_gap: s.window_gap as f32,
+            padding: s.padding,
+            gapless_monocle: s.gapless_monocle,
+            borderless_monocle: s.borderless_monocle,
+            single_monocle: s.single_monocle,
+        });
+        self.layout_changed();
+    }
+
+    /// Padding of a monitor or desktop
+    fn local_config(
+        &mut self,
+        target: ConfigTarget,
+        name: &str,
+        value: Option<String>,
+    ) -> Result<String> {
+        let padding = match target {
+            ConfigTarget::Monitor(sel) => {
+                let output = match sel {
+                    MonitorSelector::Focused => self.wm.focused_output(),
+                    MonitorSelector::Id(id) => Some(id),
+                };
+                let output = output.ok_or_else(|| "No such monitor".to_string())?;
+                self.wm.output_padding(output)?
+            }
+            ConfigTarget::Desktop(sel) => {
+                let workspace = self.select_desktop(&sel)?;
+                self.wm.workspace_padding(workspace)?
+            }
+        };
+        match value {
+            None => Ok(format!("{}\n", padding.get(name)?)),
+            Some(value) => {
+                padding.set(name, &value)?;
+                self.wm.configure_views();
+                self.layout_changed();
+                Ok(String::new())
+            }
+        }
+    }
+
+    /// Pointer to the middle of the window
+    fn warp_pointer_to(&mut self, id: NodeId) {
+        let Some(viewref) = self.wm.find_view(id) else {
+            return;
+        };
+        let rect = viewref.content_and_rect().1;
+        let x = (rect.x + rect.w / 2.0) as f64;
+        let y = (rect.y + rect.h / 2.0) as f64;
+        std::mem::drop(viewref);
+        unsafe {
+            wl::wlr_cursor_warp(self.cursor, ptr::null_mut(), x, y);
+            handle_cursor_move(self, now_msec());
+        }
+    }
+
+    /// Give keyboard focus to the active node of the focused workspace, if there is one
+    pub fn refocus(&mut self) {
+        match self.wm.active_node() {
+            Some(node) => self.focus_node(node.id),
+            None => {
+                unsafe { wl::wlr_seat_keyboard_clear_focus(self.seat) };
+                self.update_pointer_constraint();
+            }
+        }
+    }
+}
+
+fn keysym_from_name(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let flags = wl::xkb_keysym_flags_XKB_KEYSYM_NO_FLAGS;
+    let sym = unsafe { wl::xkb_keysym_from_name(name.as_ptr(), flags) };
+    // XKB_KEY_NoSymbol
+    if sym == 0 {
+        None
+    } else {
+        Some(sym)
+    }
+}
@@ -0,0 +1,120 @@
+// Pointer constraints (lock and confine) for games and 3D tools. Only the constraint of the
+// surface with keyboard focus is active, and only while the pointer is on that surface.
+
+use std::ptr;
+
+use wl_sys as wl;
+
+use super::runner::{cursor_pos, find_window};
+use super::server::{listen_server_signal, Listener, Server};
+
+pub struct Constraint {
+    pub constraint: *mut wl::wlr_pointer_constraint_v1,
+    pub destroy: Listener<(), u8>,
+    pub id: u8,
+}
+
+impl Server {
+    /// Activate the constraint of the focused surface, and deactivate the old one
+    pub fn update_pointer_constraint(&mut self) {
+        unsafe {
+            let surface = (*self.seat).keyboard_state.focused_surface;
+            let constraint = if surface.is_null() {
+                ptr::null_mut()
+            } else {
+                wl::wlr_pointer_constraints_v1_constraint_for_surface(
+                    self.pointer_constraints,
+                    surface,
+                    self.seat,
+                )
+            };
+            if constraint == self.active_constraint {
+                return;
+            }
+
+            if !self.active_constraint.is_null() {
+                wl::wlr_pointer_constraint_v1_send_deactivated(self.active_constraint);
+            }
+            self.active_constraint = constraint;
+            if !constraint.is_null() {
+                println!("Pointer constraint activated");
+                wl::wlr_pointer_constraint_v1_send_activated(constraint);
+            }
+        }
+    }
+
+    /// How much the pointer can move, locked doesn't move at all and confined stays in the region.
+    /// Keyboard focus activates the constraint, but it holds the pointer only inside the region.
+    pub unsafe fn constrain_motion(&self, dx: f64, dy: f64) -> (f64, f64) {
+        let constraint = self.active_constraint;
+        if constraint.is_null()
+            || (*self.seat).pointer_state.focused_surface != (*constraint).surface
+        {
+            return (dx, dy);
+        }
+
+        let Some((_, surface, p)) = find_window(self, cursor_pos(self)) else {
+            return (dx, dy);
+        };
+        let in_region = wl::pixman_region32_contains_point(
+            &mut (*constraint).region,
+            p.x.floor() as i32,
+            p.y.floor() as i32,
+            ptr::null_mut(),
+        ) != 0;
+        if surface != (*constraint).surface || !in_region {
+            return (dx, dy);
+        }
+
+        if (*constraint).type_
+            == wl::wlr_pointer_constraint_v1_type_WLR_POINTER_CONSTRAINT_V1_LOCKED
+        {
+            return (0.0, 0.0);
+        }
+        let (mut x, mut y) = (0.0, 0.0);
+        let confined = wl::wlr_region_confine(
+            &mut (*constraint).region,
+            p.x,
+            p.y,
+            p.x + dx,
+            p.y + dy,
+            &mut x,
+            &mut y,
+        );
+        if confined {
+            (x - p.x, y - p.y)
+        } else {
+            (0.0, 0.0)
+        }
+    }
+}
+
+pub fn new_constraint(server: &mut Server, constraint: &mut wl::wlr_pointer_constraint_v1, _: ()) {
+    let Some(id) = (1..255).find(|i| server.constraints.iter().all(|x| x.id != *i)) else {
+        eprintln!("Too many pointer constraints");
+        return;
+    };
+    let mut c = Box::pin(Constraint {
+        constraint,
+        destroy: Listener::new(constraint_destroy, id),
+        id,
+    });
+    unsafe {
+        let x = c.as_mut().get_unchecked_mut();
+        listen_server_signal(&mut constraint.events.destroy, &mut x.destroy);
+    }
+    server.constraints.push(c);
+
+    // Might be for the focused surface already
+    server.update_pointer_constraint();
+}
+
+fn constraint_destroy(server: &mut Server, _: &mut (), id: u8) {
+    let Some(i) = server.constraints.iter().position(|x| x.id == id) else {
+        return;
+    };
+    let c = server.constraints.remove(i);
+    if c.constraint == server.active_constraint {
+        server.active_constraint = ptr::null_mut();
+    }
+}
@@ -0,0 +1,140 @@
+// Touchpad gestures. They go to the client under the pointer with pointer-gestures-v1, except
+// swipes that are bound: those are ours from the start, and the binding for the direction runs
+// when the fingers are lifted.
+
+use wl_sys as wl;
+
+use crate::bindings::swipe_direction;
+
+use super::runner::keyboard_modifiers;
+use super::server::Server;
+
+/// Swipe that is ours
+pub struct BoundSwipe {
+    fingers: u32,
+    modifiers: u32,
+    dx: f64,
+    dy: f64,
+}
+
+pub fn swipe_begin(server: &mut Server, event: &mut wl::wlr_event_pointer_swipe_begin, _: ()) {
+    let modifiers = keyboard_modifiers(server);
+    if server.bindings.has_swipe(event.fingers, modifiers) {
+        server.swipe = Some(BoundSwipe {
+            fingers: event.fingers,
+            modifiers,
+            dx: 0.0,
+            dy: 0.0,
+        });
+        return;
+    }
+    unsafe {
+        wl::wlr_pointer_gestures_v1_send_swipe_begin(
+            server.pointer_gestures,
+            server.seat,
+            event.time_msec,
+            event.fingers,
+        );
+    }
+}
+
+pub fn swipe_update(server: &mut Server, event: &mut wl::wlr_event_pointer_swipe_update, _: ()) {
+    if let Some(swipe) = server.swipe.as_mut() {
+        swipe.dx += event.dx;
+        swipe.dy += event.dy;
+        return;
+    }
+    unsafe {
+        wl::wlr_pointer_gestures_v1_send_swipe_update(
+            server.pointer_gestures,
+            server.seat,
+            event.time_msec,
+            event.dx,
+            event.dy,
+        );
+    }
+}
+
+pub fn swipe_end(server: &mut Server, event: &mut wl::wlr_event_pointer_swipe_end, _: ()) {
+    let Some(swipe) = server.swipe.take() else {
+        unsafe {
+            wl::wlr_pointer_gestures_v1_send_swipe_end(
+                server.pointer_gestures,
+                server.seat,
+                event.time_msec,
+                event.cancelled,
+            );
+        }
+        return;
+    };
+    if event.cancelled {
+        return;
+    }
+
+    let command = swipe_direction(swipe.dx, swipe.dy)
+        .and_then(|dir| server.bindings.swipe(swipe.fingers, dir, swipe.modifiers));
+    if let Some(command) = command {
+        println!("Swipe binding: {}", command);
+        if let Err(e) = server.run_command_line(&command) {
+            println!("Swipe binding failed: {}", e);
+        }
+    }
+}
+
+pub fn pinch_begin(server: &mut Server, event: &mut wl::wlr_event_pointer_pinch_begin, _: ()) {
+    unsafe {
+        wl::wlr_pointer_gestures_v1_send_pinch_begin(
+            server.pointer_gestures,
+            server.seat,
+            event.time_msec,
+            event.fingers,
+        );
+    }
+}
+
+pub fn pinch_update(server: &mut Server, event: &mut wl::wlr_event_pointer_pinch_update, _: ()) {
+    unsafe {
+        wl::wlr_pointer_gestures_v1_send_pinch_update(
+            server.pointer_gestures,
+            server.seat,
+            event.time_msec,
+            event.dx,
+            event.dy,
+            event.scale,
+            event.rotation,
+        );
+    }
+}
+
+pub fn pinch_end(server: &mut Server, event: &mut wl::wlr_event_pointer_pinch_end, _: ()) {
+    unsafe {
+        wl::wlr_pointer_gestures_v1_send_pinch_end(
+            server.pointer_gestures,
+            server.seat,
+            event.time_msec,
+            event.cancelled,
+        );
+    }
+}
+
+pub fn hold_begin(server: &mut Server, event: &mut wl::wlr_event_pointer_hold_begin, _: ()) {
+    unsafe {
+        wl::wlr_pointer_gestures_v1_send_hold_begin(
+            server.pointer_gestures,
+            server.seat,
+            event.time_msec,
+            event.fingers,
+        );
+    }
+}
+
+pub fn hold_end(server: &mut Server, event: &mut wl::wlr_event_pointer_hold_end, _: ()) {
+    unsafe {
+        wl::wlr_pointer_gestures_v1_send_hold_end(
+            server.pointer_gestures,
+            server.seat,
+            event.time_msec,
+            event.cancelled,
+        );
+    }
+}
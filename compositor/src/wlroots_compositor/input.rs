@@ -0,0 +1,426 @@
+// Applying input config to the devices
+
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use wl_sys as wl;
+
+use crate::command::LayoutGroup;
+use crate::input_config::{AccelProfile, DeviceConfig, ScrollMethod};
+use crate::types::Result;
+
+use super::server::Server;
+
+const DEFAULT_REPEAT_RATE: i32 = 25;
+const DEFAULT_REPEAT_DELAY: i32 = 600;
+
+impl Server {
+    /// Apply the config to all devices
+    pub fn configure_inputs(&self) -> Result<()> {
+        let keyboards = self.configure_keyboards();
+        let pointers = self.configure_pointers();
+        self.map_to_outputs();
+        keyboards.and(pointers)
+    }
+
+    /// Apply the config to all keyboards
+    pub fn configure_keyboards(&self) -> Result<()> {
+        let mut result = Ok(());
+        for keyboard in self.keyboards.iter() {
+            if let Err(e) = unsafe { self.configure_keyboard(keyboard.device) } {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Keymap and repeat. Keyboard is left as it was if the keymap can't be made.
+    pub unsafe fn configure_keyboard(&self, device: *mut wl::wlr_input_device) -> Result<()> {
+        let name = device_name(device);
+        let config = self
+            .input_config
+            .for_device(&name, &device_identifier(device));
+
+        let c_string = |value: &Option<String>| {
+            value
+                .as_ref()
+                .map(|x| CString::new(x.as_str()))
+                .transpose()
+                .map_err(|_| format!("Invalid xkb setting for {}", name))
+        };
+        let rules = c_string(&config.xkb_rules)?;
+        let model = c_string(&config.xkb_model)?;
+        let layout = c_string(&config.xkb_layout)?;
+        let variant = c_string(&config.xkb_variant)?;
+        let options = c_string(&config.xkb_options)?;
+
+        // Not set ones are nulls, xkb picks the defaults for those
+        let as_ptr = |x: &Option<CString>| x.as_ref().map_or(ptr::null(), |x| x.as_ptr());
+        let names = wl::xkb_rule_names {
+            rules: as_ptr(&rules),
+            model: as_ptr(&model),
+            layout: as_ptr(&layout),
+            variant: as_ptr(&variant),
+            options: as_ptr(&options),
+        };
+
+        let keyboard = (*device).__bindgen_anon_1.keyboard;
+        if !set_keymap(keyboard, &names) {
+            return Err(format!("Can't make keymap for {} from {:?}", name, config));
+        }
+        wl::wlr_keyboard_set_repeat_info(
+            keyboard,
+            config.repeat_rate.unwrap_or(DEFAULT_REPEAT_RATE),
+            config.repeat_delay.unwrap_or(DEFAULT_REPEAT_DELAY),
+        );
+        Ok(())
+    }
+
+    pub fn configure_pointers(&self) -> Result<()> {
+        let mut result = Ok(());
+        for pointer in self.pointers.iter() {
+            if let Err(e) = unsafe { self.configure_pointer(pointer.device) } {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Only libinput devices can be configured, others are left as they are. Settings that are
+    /// not set go back to the device defaults, and ones the device doesn't have are skipped, so
+    /// they can be set for all devices.
+    pub unsafe fn configure_pointer(&self, device: *mut wl::wlr_input_device) -> Result<()> {
+        if !wl::wlr_input_device_is_libinput(device) {
+            return Ok(());
+        }
+        let handle = wl::wlr_libinput_get_device_handle(device);
+        let name = device_name(device);
+        let mut config = libinput_settings(handle, true);
+        config.merge(
+            &self
+                .input_config
+                .for_device(&name, &device_identifier(device)),
+        );
+
+        let mut statuses = Vec::new();
+        if let Some(tap) = config.tap {
+            let state = if tap {
+                wl::libinput_config_tap_state_LIBINPUT_CONFIG_TAP_ENABLED
+            } else {
+                wl::libinput_config_tap_state_LIBINPUT_CONFIG_TAP_DISABLED
+            };
+            statuses.push((
+                "tap",
+                wl::libinput_device_config_tap_set_enabled(handle, state),
+            ));
+        }
+        if let Some(natural) = config.natural_scroll {
+            let status = wl::libinput_device_config_scroll_set_natural_scroll_enabled(
+                handle,
+                natural as i32,
+            );
+            statuses.push(("natural_scroll", status));
+        }
+        if let Some(profile) = config.accel_profile {
+            let profile = match profile {
+                AccelProfile::Flat => {
+                    wl::libinput_config_accel_profile_LIBINPUT_CONFIG_ACCEL_PROFILE_FLAT
+                }
+                AccelProfile::Adaptive => {
+                    wl::libinput_config_accel_profile_LIBINPUT_CONFIG_ACCEL_PROFILE_ADAPTIVE
+                }
+            };
+            let status = wl::libinput_device_config_accel_set_profile(handle, profile);
+            statuses.push(("accel_profile", status));
+        }
+        if let Some(speed) = config.accel_speed {
+            let status = wl::libinput_device_config_accel_set_speed(handle, speed);
+            statuses.push(("accel_speed", status));
+        }
+        if let Some(dwt) = config.dwt {
+            let state = if dwt {
+                wl::libinput_config_dwt_state_LIBINPUT_CONFIG_DWT_ENABLED
+            } else {
+                wl::libinput_config_dwt_state_LIBINPUT_CONFIG_DWT_DISABLED
+            };
+            statuses.push((
+                "dwt",
+                wl::libinput_device_config_dwt_set_enabled(handle, state),
+            ));
+        }
+        if let Some(left_handed) = config.left_handed {
+            let status = wl::libinput_device_config_left_handed_set(handle, left_handed as i32);
+            statuses.push(("left_handed", status));
+        }
+        if let Some(middle) = config.middle_emulation {
+            let state = if middle {
+                wl::libinput_config_middle_emulation_state_LIBINPUT_CONFIG_MIDDLE_EMULATION_ENABLED
+            } else {
+                wl::libinput_config_middle_emulation_state_LIBINPUT_CONFIG_MIDDLE_EMULATION_DISABLED
+            };
+            let status = wl::libinput_device_config_middle_emulation_set_enabled(handle, state);
+            statuses.push(("middle_emulation", status));
+        }
+        if let Some(method) = config.scroll_method {
+            let method = match method {
+                ScrollMethod::None => {
+                    wl::libinput_config_scroll_method_LIBINPUT_CONFIG_SCROLL_NO_SCROLL
+                }
+                ScrollMethod::TwoFinger => {
+                    wl::libinput_config_scroll_method_LIBINPUT_CONFIG_SCROLL_2FG
+                }
+                ScrollMethod::Edge => wl::libinput_config_scroll_method_LIBINPUT_CONFIG_SCROLL_EDGE,
+                ScrollMethod::OnButtonDown => {
+                    wl::libinput_config_scroll_method_LIBINPUT_CONFIG_SCROLL_ON_BUTTON_DOWN
+                }
+            };
+            let status = wl::libinput_device_config_scroll_set_method(handle, method);
+            statuses.push(("scroll_method", status));
+        }
+
+        for (setting, status) in statuses {
+            if status == wl::libinput_config_status_LIBINPUT_CONFIG_STATUS_INVALID {
+                return Err(format!("Invalid {} for {}", setting, name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Touchscreens and tablets cover one output
+    pub fn map_to_outputs(&self) {
+        let touch = self.touch_devices.iter().map(|t| t.device);
+        let tablets = self.tablets.iter().map(|t| t.device);
+        for device in touch.chain(tablets) {
+            unsafe { self.map_to_output(device) };
+        }
+    }
+
+    /// Device goes to map_to_output, the output the device says it's on, or the first one
+    unsafe fn map_to_output(&self, device: *mut wl::wlr_input_device) {
+        let name = device_name(device);
+        let config = self
+            .input_config
+            .for_device(&name, &device_identifier(device));
+        let output_name = config.map_to_output.or_else(|| {
+            let output_name = (*device).output_name;
+            (!output_name.is_null()).then(|| CStr::from_ptr(output_name).to_string_lossy().into())
+        });
+
+        let output = self
+            .outputs
+            .iter()
+            .find(|o| {
+                let o_name = CStr::from_ptr((*o.wlr_output).name).to_string_lossy();
+                output_name.as_deref() == Some(&*o_name)
+            })
+            .or_else(|| self.outputs.first());
+        let wlr_output = output.map_or(ptr::null_mut(), |o| o.wlr_output);
+        wl::wlr_cursor_map_input_to_output(self.cursor, device, wlr_output);
+    }
+
+    /// Devices by identifier, with the settings in effect. For libinput devices those are read
+    /// from the device.
+    pub fn list_inputs(&self) -> String {
+        let mut out = String::new();
+        unsafe {
+            let keyboards = self.keyboards.iter().map(|kb| (kb.device, "keyboard"));
+            let pointers = self.pointers.iter().map(|p| (p.device, "pointer"));
+            let touch = self.touch_devices.iter().map(|t| (t.device, "touch"));
+            let tablets = self.tablets.iter().map(|t| (t.device, "tablet"));
+            let pads = self.tablet_pads.iter().map(|p| (p.device, "tablet_pad"));
+            let devices = keyboards
+                .chain(pointers)
+                .chain(touch)
+                .chain(tablets)
+                .chain(pads);
+            for (device, kind) in devices {
+                let name = device_name(device);
+                let identifier = device_identifier(device);
+                let mut config = self.input_config.for_device(&name, &identifier);
+                if kind == "pointer" && wl::wlr_input_device_is_libinput(device) {
+                    config = libinput_settings(wl::wlr_libinput_get_device_handle(device), false);
+                }
+
+                out += &format!("{}\n  type {}\n  name {}\n", identifier, kind, name);
+                for line in config.list().lines() {
+                    out += &format!("  {}\n", line);
+                }
+            }
+        }
+        out
+    }
+
+    /// Switch layout of the keyboard with given name or identifier, or all of them
+    pub fn switch_layout_group(&self, device: Option<&str>, group: LayoutGroup) -> Result<()> {
+        let mut found = false;
+        for kb in self.keyboards.iter() {
+            unsafe {
+                let matches = |d: &str| {
+                    d == device_name(kb.device) || d == device_identifier(kb.device)
+                };
+                if device.map_or(false, |d| !matches(d)) {
+                    continue;
+                }
+                found = true;
+
+                let keyboard = (*kb.device).__bindgen_anon_1.keyboard;
+                let layouts = wl::xkb_keymap_num_layouts((*keyboard).keymap);
+                if layouts == 0 {
+                    continue;
+                }
+                let current = (*keyboard).modifiers.group;
+                let next = match group {
+                    LayoutGroup::Next => (current + 1) % layouts,
+                    LayoutGroup::Prev => (current + layouts - 1) % layouts,
+                    LayoutGroup::Index(n) if n <= layouts => n - 1,
+                    LayoutGroup::Index(n) => {
+                        return Err(format!("Keyboard has only {} layouts, not {}", layouts, n))
+                    }
+                };
+
+                // Comes back to us as a modifiers event, which tells the clients
+                let m = &(*keyboard).modifiers;
+                wl::wlr_keyboard_notify_modifiers(keyboard, m.depressed, m.latched, m.locked, next);
+            }
+        }
+        if found {
+            Ok(())
+        } else {
+            Err("No such keyboard".to_string())
+        }
+    }
+}
+
+/// Names of all xkb settings are null for the default keymap
+pub unsafe fn set_keymap(
+    keyboard: *mut wl::wlr_keyboard,
+    names: *const wl::xkb_rule_names,
+) -> bool {
+    let context = wl::xkb_context_new(wl::xkb_context_flags_XKB_CONTEXT_NO_FLAGS);
+    let keymap = wl::xkb_keymap_new_from_names(
+        context,
+        names,
+        wl::xkb_keymap_compile_flags_XKB_KEYMAP_COMPILE_NO_FLAGS,
+    );
+    wl::xkb_context_unref(context);
+
+    if keymap.is_null() {
+        return false;
+    }
+    wl::wlr_keyboard_set_keymap(keyboard, keymap);
+    wl::xkb_keymap_unref(keymap);
+    true
+}
+
+/// What the device has now, or its defaults. Settings it doesn't have are None.
+unsafe fn libinput_settings(handle: *mut wl::libinput_device, defaults: bool) -> DeviceConfig {
+    let mut config = DeviceConfig::default();
+    if wl::libinput_device_config_tap_get_finger_count(handle) > 0 {
+        let state = if defaults {
+            wl::libinput_device_config_tap_get_default_enabled(handle)
+        } else {
+            wl::libinput_device_config_tap_get_enabled(handle)
+        };
+        config.tap = Some(state == wl::libinput_config_tap_state_LIBINPUT_CONFIG_TAP_ENABLED);
+    }
+    if wl::libinput_device_config_scroll_has_natural_scroll(handle) != 0 {
+        let enabled = if defaults {
+            wl::libinput_device_config_scroll_get_default_natural_scroll_enabled(handle)
+        } else {
+            wl::libinput_device_config_scroll_get_natural_scroll_enabled(handle)
+        };
+        config.natural_scroll = Some(enabled != 0);
+    }
+    if wl::libinput_device_config_accel_is_available(handle) != 0 {
+        let (speed, profile) = if defaults {
+            (
+                wl::libinput_device_config_accel_get_default_speed(handle),
+                wl::libinput_device_config_accel_get_default_profile(handle),
+            )
+        } else {
+            (
+                wl::libinput_device_config_accel_get_speed(handle),
+                wl::libinput_device_config_accel_get_profile(handle),
+            )
+        };
+        config.accel_speed = Some(speed);
+        config.accel_profile = match profile {
+            wl::libinput_config_accel_profile_LIBINPUT_CONFIG_ACCEL_PROFILE_FLAT => {
+                Some(AccelProfile::Flat)
+            }
+            wl::libinput_config_accel_profile_LIBINPUT_CONFIG_ACCEL_PROFILE_ADAPTIVE => {
+                Some(AccelProfile::Adaptive)
+            }
+            _ => None,
+        };
+    }
+    if wl::libinput_device_config_dwt_is_available(handle) != 0 {
+        let state = if defaults {
+            wl::libinput_device_config_dwt_get_default_enabled(handle)
+        } else {
+            wl::libinput_device_config_dwt_get_enabled(handle)
+        };
+        config.dwt = Some(state == wl::libinput_config_dwt_state_LIBINPUT_CONFIG_DWT_ENABLED);
+    }
+    if wl::libinput_device_config_left_handed_is_available(handle) != 0 {
+        let left_handed = if defaults {
+            wl::libinput_device_config_left_handed_get_default(handle)
+        } else {
+            wl::libinput_device_config_left_handed_get(handle)
+        };
+        config.left_handed = Some(left_handed != 0);
+    }
+    if wl::libinput_device_config_middle_emulation_is_available(handle) != 0 {
+        let state = if defaults {
+            wl::libinput_device_config_middle_emulation_get_default_enabled(handle)
+        } else {
+            wl::libinput_device_config_middle_emulation_get_enabled(handle)
+        };
+        config.middle_emulation = Some(
+            state
+                == wl::libinput_config_middle_emulation_state_LIBINPUT_CONFIG_MIDDLE_EMULATION_ENABLED,
+        );
+    }
+    if wl::libinput_device_config_scroll_get_methods(handle) != 0 {
+        let method = if defaults {
+            wl::libinput_device_config_scroll_get_default_method(handle)
+        } else {
+            wl::libinput_device_config_scroll_get_method(handle)
+        };
+        config.scroll_method = match method {
+            wl::libinput_config_scroll_method_LIBINPUT_CONFIG_SCROLL_NO_SCROLL => {
+                Some(ScrollMethod::None)
+            }
+            wl::libinput_config_scroll_method_LIBINPUT_CONFIG_SCROLL_2FG => {
+                Some(ScrollMethod::TwoFinger)
+            }
+            wl::libinput_config_scroll_method_LIBINPUT_CONFIG_SCROLL_EDGE => {
+                Some(ScrollMethod::Edge)
+            }
+            wl::libinput_config_scroll_method_LIBINPUT_CONFIG_SCROLL_ON_BUTTON_DOWN => {
+                Some(ScrollMethod::OnButtonDown)
+            }
+            _ => None,
+        };
+    }
+    config
+}
+
+/// `vendor:product:name`, with spaces in the name as underscores
+pub unsafe fn device_identifier(device: *mut wl::wlr_input_device) -> String {
+    format!(
+        "{}:{}:{}",
+        (*device).vendor,
+        (*device).product,
+        device_name(device).replace(' ', "_")
+    )
+}
+
+pub unsafe fn device_name(device: *mut wl::wlr_input_device) -> String {
+    let name = (*device).name;
+    if name.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(name).to_string_lossy().into_owned()
+    }
+}
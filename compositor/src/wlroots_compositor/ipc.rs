@@ -0,0 +1,124 @@
+// IPC for rspc. Client sends the arguments, each followed by a NUL, and closes its write end. We
+// reply with text, and failures begin with FAILURE_MESSAGE (like bspwm does).
+
+use std::ffi::c_void;
+use std::io::{self, Read, Write};
+use std::os::raw::c_int;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::ptr;
+use std::time::Duration;
+
+use wl_sys as wl;
+
+use crate::command;
+
+use super::server::{server_ptr, Server};
+
+const FAILURE_MESSAGE: char = '\x07';
+
+// From wayland-server-core.h. It's an anonymous enum, so it doesn't get to the bindings.
+pub const WL_EVENT_READABLE: u32 = 0x01;
+
+pub struct Ipc {
+    listener: UnixListener,
+    path: PathBuf,
+    event_source: *mut wl::wl_event_source,
+}
+
+pub fn socket_path(wayland_display_name: &str) -> PathBuf {
+    if let Ok(path) = std::env::var("RSPWL_SOCKET") {
+        return path.into();
+    }
+    let dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_string());
+    PathBuf::from(dir).join(format!("rspwl-{}.sock", wayland_display_name))
+}
+
+impl Ipc {
+    pub unsafe fn start(wl_display: *mut wl::wl_display, wayland_display_name: &str) -> io::Result<Ipc> {
+        let path = socket_path(wayland_display_name);
+
+        // Might be left there by a crashed compositor
+        let _ = std::fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+
+        let event_loop = wl::wl_display_get_event_loop(wl_display);
+        let event_source = wl::wl_event_loop_add_fd(
+            event_loop,
+            listener.as_raw_fd(),
+            WL_EVENT_READABLE,
+            Some(ipc_readable),
+            ptr::null_mut(),
+        );
+
+        // So that rspc started by us (or by our children) knows where to go
+        std::env::set_var("RSPWL_SOCKET", &path);
+
+        println!("IPC socket at {}", path.display());
+
+        Ok(Ipc {
+            listener,
+            path,
+            event_source,
+        })
+    }
+}
+
+impl Drop for Ipc {
+    fn drop(&mut self) {
+        unsafe {
+            wl::wl_event_source_remove(self.event_source);
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+unsafe extern "C" fn ipc_readable(_fd: c_int, _mask: u32, _data: *mut c_void) -> c_int {
+    let server = &mut *server_ptr();
+
+    loop {
+        let accepted = match &server.ipc {
+            Some(ipc) => ipc.listener.accept(),
+            None => break,
+        };
+        match accepted {
+            Ok((stream, _)) => handle_client(server, stream),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => {
+                eprintln!("IPC accept failed: {}", e);
+                break;
+            }
+        }
+    }
+
+    0
+}
+
+fn handle_client(server: &mut Server, mut stream: UnixStream) {
+    // rspc sends everything right away, so it's ok to block for a moment
+    let mut msg = Vec::new();
+    let read = stream
+        .set_nonblocking(false)
+        .and_then(|_| stream.set_read_timeout(Some(Duration::from_millis(500))))
+        .and_then(|_| stream.read_to_end(&mut msg));
+    if let Err(e) = read {
+        eprintln!("IPC read failed: {}", e);
+        return;
+    }
+
+    let msg = String::from_utf8_lossy(&msg);
+    let args: Vec<&str> = msg.split_terminator('\0').collect();
+    println!("IPC: {:?}", args);
+
+    let reply = match command::parse(&args).and_then(|c| server.execute_command(c)) {
+        Ok(reply) => reply,
+        Err(e) => format!("{}{}\n", FAILURE_MESSAGE, e),
+    };
+
+    if let Err(e) = stream.write_all(reply.as_bytes()) {
+        eprintln!("IPC write failed: {}", e);
+    }
+}
@@ -1,5 +1,13 @@
+mod commands;
+mod constraints;
+mod gestures;
+mod input;
+mod ipc;
+mod placement;
 pub mod runner;
 mod server;
+mod tablet;
+mod touch;
 mod wl_util;
 
 pub use server::{OutputId, View};
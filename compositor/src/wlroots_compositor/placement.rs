@@ -0,0 +1,305 @@
+// Deciding where a toplevel goes when it maps: rules, dialogs and what the client asked for.
+// External rules come from a process, the window waits unplaced until it's done, and everything
+// else goes on meanwhile.
+
+use std::ffi::{c_void, CStr};
+use std::io::Read;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::io::AsRawFd;
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+use wl_sys as wl;
+
+use crate::command::MonitorSelector;
+use crate::rules::Consequences;
+use crate::types::{NodeId, Result, WorkspaceId};
+use crate::window_manager::{Flag, Placement, WindowState};
+
+use super::ipc::WL_EVENT_READABLE;
+use super::server::{server_ptr, Server};
+
+/// How long external_rules_command gets until the window is placed without it
+const EXTERNAL_RULES_TIMEOUT_MS: c_int = 500;
+
+/// Toplevel that waits for external_rules_command
+pub struct PendingRules {
+    id: NodeId,
+    xdg_surface: *mut wl::wlr_xdg_surface,
+    /// From our own rules, the external ones go on top
+    consequences: Consequences,
+    child: Child,
+    stdout: ChildStdout,
+    output: Vec<u8>,
+    readable: *mut wl::wl_event_source,
+    timer: *mut wl::wl_event_source,
+}
+
+impl Drop for PendingRules {
+    fn drop(&mut self) {
+        unsafe {
+            wl::wl_event_source_remove(self.readable);
+            wl::wl_event_source_remove(self.timer);
+        }
+        // Don't leave zombies behind
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+impl Server {
+    pub unsafe fn map_toplevel(&mut self, id: NodeId, xdg_surface: *mut wl::wlr_xdg_surface) {
+        let toplevel = &*(*xdg_surface).__bindgen_anon_1.toplevel;
+        let app_id = string_or_empty(toplevel.app_id);
+        let title = string_or_empty(toplevel.title);
+        println!("Map {} app_id {:?} title {:?}", id, app_id, title);
+
+        if self.pending_rules.iter().any(|p| p.id == id) {
+            return;
+        }
+        let consequences = self.rules.apply(&app_id, &title);
+        if !self.settings.external_rules_command.is_empty() {
+            let pid = client_pid(xdg_surface);
+            match self.start_external_rules(id, &app_id, &title, pid) {
+                Ok((child, stdout)) => {
+                    self.wait_for_external_rules(id, xdg_surface, consequences, child, stdout);
+                    return;
+                }
+                Err(e) => println!("Failed to run external rules for {}: {}", id, e),
+            }
+        }
+
+        self.place_toplevel(id, xdg_surface, &consequences);
+    }
+
+    unsafe fn place_toplevel(
+        &mut self,
+        id: NodeId,
+        xdg_surface: *mut wl::wlr_xdg_surface,
+        consequences: &Consequences,
+    ) {
+        let toplevel = &*(*xdg_surface).__bindgen_anon_1.toplevel;
+        let placement = match self.toplevel_placement(xdg_surface, consequences) {
+            Ok(placement) => placement,
+            Err(e) => {
+                println!("Rules for {} failed: {}", id, e);
+                self.toplevel_placement(xdg_surface, &Consequences::default())
+                    .expect("Default placement can't fail")
+            }
+        };
+        let workspace = placement.workspace;
+        let focus = placement.focus;
+
+        if let Err(e) = self.wm.map_view(id, placement) {
+            println!("Mapping {} failed: {}", id, e);
+            return;
+        }
+
+        if toplevel.requested.maximized && !self.settings.ignore_maximize_requests.ignores(true) {
+            let _ = self.wm.set_maximized(id, true);
+        }
+        if let Some(layer) = consequences.layer {
+            let _ = self.wm.set_layer(id, layer);
+        }
+        for (flag, value) in consequences.flags.iter() {
+            let _ = self.wm.set_flag(id, *flag, Some(*value));
+        }
+        let hidden = self.wm.flag(id, Flag::Hidden).unwrap_or(false);
+
+        if consequences.follow == Some(true) {
+            if let Some(workspace) = workspace {
+                let _ = self.wm.focus_workspace(workspace);
+            }
+        }
+        if focus
+            && !hidden
+            && self.wm.workspace_of_view(id).ok() == Some(self.wm.focused_workspace())
+        {
+            self.focus_node(id);
+        } else {
+            self.refocus();
+        }
+
+        self.layout_changed();
+    }
+
+    unsafe fn toplevel_placement(
+        &self,
+        xdg_surface: *mut wl::wlr_xdg_surface,
+        consequences: &Consequences,
+    ) -> Result<Placement> {
+        let toplevel = &*(*xdg_surface).__bindgen_anon_1.toplevel;
+
+        let parent = if toplevel.parent.is_null() {
+            None
+        } else {
+            self.find_xdg_view(toplevel.parent)
+        };
+
+        // Dialogs and windows that can't be resized float
+        let hints = &toplevel.current;
+        let fixed_size = hints.min_width > 0
+            && hints.min_height > 0
+            && hints.min_width == hints.max_width
+            && hints.min_height == hints.max_height;
+
+        // It might have asked before it got mapped
+        let fullscreen = toplevel.requested.fullscreen
+            && !self.settings.ignore_fullscreen_requests.ignores(true);
+
+        let state = match consequences.state {
+            Some(state) => state,
+            None if fullscreen => WindowState::Fullscreen,
+            None if parent.is_some() || fixed_size => WindowState::Floating,
+            None => WindowState::Tiled,
+        };
+
+        Ok(Placement {
+            parent,
+            workspace: self.rule_workspace(consequences)?,
+            state,
+            split_dir: consequences.split_dir,
+            split_ratio: consequences.split_ratio,
+            focus: consequences.focus.unwrap_or(true),
+        })
+    }
+
+    /// Start external_rules_command with window id, app_id, title and pid. It prints consequences
+    /// like `desktop=web state=floating`, separated by whitespace.
+    fn start_external_rules(
+        &self,
+        id: NodeId,
+        app_id: &str,
+        title: &str,
+        pid: i32,
+    ) -> std::io::Result<(Child, ChildStdout)> {
+        let mut child = Command::new(&self.settings.external_rules_command)
+            .arg(format!("{:#x}", id))
+            .arg(app_id)
+            .arg(title)
+            .arg(pid.to_string())
+            .env("WAYLAND_DISPLAY", &self.wayland_display_name)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().expect("Stdout is piped");
+        Ok((child, stdout))
+    }
+
+    /// Read the output as it comes, and place the window when the command is done or the time is
+    /// up. It's not waited for, so it can even use rspc.
+    unsafe fn wait_for_external_rules(
+        &mut self,
+        id: NodeId,
+        xdg_surface: *mut wl::wlr_xdg_surface,
+        consequences: Consequences,
+        child: Child,
+        stdout: ChildStdout,
+    ) {
+        let event_loop = wl::wl_display_get_event_loop(self.wl_display);
+        let data = id as usize as *mut c_void;
+        let readable = wl::wl_event_loop_add_fd(
+            event_loop,
+            stdout.as_raw_fd(),
+            WL_EVENT_READABLE,
+            Some(external_rules_readable),
+            data,
+        );
+        let timer = wl::wl_event_loop_add_timer(event_loop, Some(external_rules_timeout), data);
+        wl::wl_event_source_timer_update(timer, EXTERNAL_RULES_TIMEOUT_MS);
+
+        self.pending_rules.push(PendingRules {
+            id,
+            xdg_surface,
+            consequences,
+            child,
+            stdout,
+            output: Vec::new(),
+            readable,
+            timer,
+        });
+    }
+
+    /// Place the window with what external_rules_command said, if it got to say anything
+    unsafe fn finish_external_rules(&mut self, id: NodeId, timed_out: bool) {
+        let Some(i) = self.pending_rules.iter().position(|p| p.id == id) else { return; };
+        let mut pending = self.pending_rules.remove(i);
+
+        if timed_out {
+            println!("External rules for {} took too long, killed it", id);
+        } else {
+            let command = &self.settings.external_rules_command;
+            for c in String::from_utf8_lossy(&pending.output).split_whitespace() {
+                if let Err(e) = pending.consequences.set(c) {
+                    println!("{}: {}", command, e);
+                }
+            }
+        }
+
+        let xdg_surface = pending.xdg_surface;
+        let consequences = std::mem::take(&mut pending.consequences);
+        drop(pending);
+        self.place_toplevel(id, xdg_surface, &consequences);
+    }
+
+    /// Window went away before its external rules were done
+    pub fn forget_pending_rules(&mut self, id: NodeId) {
+        self.pending_rules.retain(|p| p.id != id);
+    }
+
+    /// Desktop wins over monitor
+    fn rule_workspace(&self, consequences: &Consequences) -> Result<Option<WorkspaceId>> {
+        if let Some(desktop) = &consequences.desktop {
+            return self.select_desktop(desktop).map(Some);
+        }
+        match consequences.monitor {
+            Some(MonitorSelector::Focused) => Ok(Some(self.wm.focused_workspace())),
+            Some(MonitorSelector::Id(output)) => self
+                .wm
+                .output_workspace(output)
+                .map(Some)
+                .ok_or_else(|| format!("No monitor {}", output)),
+            None => Ok(None),
+        }
+    }
+}
+
+unsafe extern "C" fn external_rules_readable(_fd: c_int, _mask: u32, data: *mut c_void) -> c_int {
+    let server = &mut *server_ptr();
+    let id = data as usize as NodeId;
+    let Some(pending) = server.pending_rules.iter_mut().find(|p| p.id == id) else { return 0; };
+
+    // Event loop said there's something, so this doesn't block. Nothing means it's done.
+    let mut buf = [0; 4096];
+    match pending.stdout.read(&mut buf) {
+        Ok(0) => server.finish_external_rules(id, false),
+        Ok(n) => pending.output.extend_from_slice(&buf[..n]),
+        Err(e) => {
+            println!("Reading external rules for {} failed: {}", id, e);
+            server.finish_external_rules(id, false);
+        }
+    }
+    0
+}
+
+unsafe extern "C" fn external_rules_timeout(data: *mut c_void) -> c_int {
+    let server = &mut *server_ptr();
+    server.finish_external_rules(data as usize as NodeId, true);
+    0
+}
+
+unsafe fn client_pid(xdg_surface: *mut wl::wlr_xdg_surface) -> i32 {
+    let client = wl::wl_resource_get_client((*xdg_surface).resource);
+    let (mut pid, mut uid, mut gid) = (0, 0, 0);
+    wl::wl_client_get_credentials(client, &mut pid, &mut uid, &mut gid);
+    pid
+}
+
+unsafe fn string_or_empty(s: *const c_char) -> String {
+    if s.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(s).to_string_lossy().into_owned()
+    }
+}
@@ -5,16 +5,28 @@ use std::ffi::{c_void, CStr};
 use std::marker::PhantomPinned;
 use std::mem::MaybeUninit;
 use std::ptr;
+use std::time::{Duration, Instant};
 
 use wl_sys as wl;
 
 use crate::window_manager::ViewRef;
 use crate::{
+    bindings::{Bindings, KeyAction, ScrollDir, DEFAULT_BINDINGS},
+    input_config::InputConfig,
+    rules::Rules,
+    settings::{Color, Settings},
     window_manager::WindowManager,
     wlroots_compositor::server::*,
 };
 
-use super::{server::View, wl_util::*};
+use super::constraints::new_constraint;
+use super::gestures::{
+    hold_begin, hold_end, pinch_begin, pinch_end, pinch_update, swipe_begin, swipe_end,
+    swipe_update,
+};
+use super::tablet::{tool_axis, tool_button, tool_proximity, tool_tip};
+use super::touch::{touch_cancel, touch_down, touch_frame, touch_motion, touch_up};
+use super::{input::set_keymap, ipc::Ipc, server::View, wl_util::*};
 use crate::types::Rect;
 
 pub fn run_server() {
@@ -64,6 +76,18 @@ unsafe fn begin_adventure() {
         .to_string();
 
     let seat = wl::wlr_seat_create(wl_display, cstring!("seat0"));
+    let tablet_manager = wl::wlr_tablet_v2_create(wl_display);
+    let pointer_gestures = wl::wlr_pointer_gestures_v1_create(wl_display);
+    let relative_pointer_manager = wl::wlr_relative_pointer_manager_v1_create(wl_display);
+    let pointer_constraints = wl::wlr_pointer_constraints_v1_create(wl_display);
+
+    let ipc = match Ipc::start(wl_display, &wayland_display_name) {
+        Ok(ipc) => Some(ipc),
+        Err(e) => {
+            eprintln!("Failed to start IPC: {}", e);
+            None
+        }
+    };
 
     let mut server = Server {
         wayland_display_name,
@@ -82,12 +106,45 @@ unsafe fn begin_adventure() {
         cursor_button: Listener::new(cursor_button, ()),
         cursor_axis: Listener::new(cursor_axis, ()),
         cursor_frame: Listener::new(cursor_frame, ()),
+        cursor_touch_down: Listener::new(touch_down, ()),
+        cursor_touch_up: Listener::new(touch_up, ()),
+        cursor_touch_motion: Listener::new(touch_motion, ()),
+        cursor_touch_cancel: Listener::new(touch_cancel, ()),
+        cursor_touch_frame: Listener::new(touch_frame, ()),
+        cursor_tool_axis: Listener::new(tool_axis, ()),
+        cursor_tool_proximity: Listener::new(tool_proximity, ()),
+        cursor_tool_tip: Listener::new(tool_tip, ()),
+        cursor_tool_button: Listener::new(tool_button, ()),
+        cursor_swipe_begin: Listener::new(swipe_begin, ()),
+        cursor_swipe_update: Listener::new(swipe_update, ()),
+        cursor_swipe_end: Listener::new(swipe_end, ()),
+        cursor_pinch_begin: Listener::new(pinch_begin, ()),
+        cursor_pinch_update: Listener::new(pinch_update, ()),
+        cursor_pinch_end: Listener::new(pinch_end, ()),
+        cursor_hold_begin: Listener::new(hold_begin, ()),
+        cursor_hold_end: Listener::new(hold_end, ()),
+        pointer_gestures,
+        swipe: None,
+        relative_pointer_manager,
+        pointer_constraints,
+        new_constraint: Listener::new(new_constraint, ()),
+        constraints: Vec::new(),
+        active_constraint: ptr::null_mut(),
+        pointer_focus_time: None,
 
         seat,
         new_input: Listener::new(handle_new_input, ()),
         request_cursor: Listener::new(handle_request_cursor, ()),
         request_set_selection: Listener::new(handle_request_set_selection, ()),
         keyboards: Vec::new(),
+        pointers: Vec::new(),
+        touch_devices: Vec::new(),
+        touch_points: Vec::new(),
+        touch_emulation: None,
+        tablet_manager,
+        tablets: Vec::new(),
+        tablet_pads: Vec::new(),
+        tablet_tools: Vec::new(),
 
         outputs: Vec::new(),
         output_layout,
@@ -95,6 +152,13 @@ unsafe fn begin_adventure() {
         new_xdg_surface: Listener::new(new_xdg_surface, ()),
         new_output: Listener::new(new_output, ()),
 
+        ipc,
+        settings: Settings::default(),
+        rules: Rules::default(),
+        pending_rules: Vec::new(),
+        bindings: Bindings::default(),
+        input_config: InputConfig::default(),
+
         wm: WindowManager::new(),
     };
 
@@ -120,10 +184,35 @@ unsafe fn begin_adventure() {
     );
     listen_server_signal(&mut (*server.cursor).events.axis, &mut server.cursor_axis);
     listen_server_signal(&mut (*server.cursor).events.frame, &mut server.cursor_frame);
+    let events = &mut (*server.cursor).events;
+    listen_server_signal(&mut events.touch_down, &mut server.cursor_touch_down);
+    listen_server_signal(&mut events.touch_up, &mut server.cursor_touch_up);
+    listen_server_signal(&mut events.touch_motion, &mut server.cursor_touch_motion);
+    listen_server_signal(&mut events.touch_cancel, &mut server.cursor_touch_cancel);
+    listen_server_signal(&mut events.touch_frame, &mut server.cursor_touch_frame);
+    listen_server_signal(&mut events.tablet_tool_axis, &mut server.cursor_tool_axis);
+    listen_server_signal(
+        &mut events.tablet_tool_proximity,
+        &mut server.cursor_tool_proximity,
+    );
+    listen_server_signal(&mut events.tablet_tool_tip, &mut server.cursor_tool_tip);
+    listen_server_signal(&mut events.tablet_tool_button, &mut server.cursor_tool_button);
+    listen_server_signal(&mut events.swipe_begin, &mut server.cursor_swipe_begin);
+    listen_server_signal(&mut events.swipe_update, &mut server.cursor_swipe_update);
+    listen_server_signal(&mut events.swipe_end, &mut server.cursor_swipe_end);
+    listen_server_signal(&mut events.pinch_begin, &mut server.cursor_pinch_begin);
+    listen_server_signal(&mut events.pinch_update, &mut server.cursor_pinch_update);
+    listen_server_signal(&mut events.pinch_end, &mut server.cursor_pinch_end);
+    listen_server_signal(&mut events.hold_begin, &mut server.cursor_hold_begin);
+    listen_server_signal(&mut events.hold_end, &mut server.cursor_hold_end);
     listen_server_signal(
         &mut (*server.backend).events.new_input,
         &mut server.new_input,
     );
+    listen_server_signal(
+        &mut (*server.pointer_constraints).events.new_constraint,
+        &mut server.new_constraint,
+    );
     listen_server_signal(
         &mut (*server.seat).events.request_set_cursor,
         &mut server.request_cursor,
@@ -136,12 +225,18 @@ unsafe fn begin_adventure() {
     set_server(&mut server);
 
     if !wl::wlr_backend_start(server.backend) {
+        server.ipc = None;
         wl::wlr_backend_destroy(server.backend);
         wl::wl_display_destroy(server.wl_display);
     } else {
+        run_config(&mut server);
+
         println!("Run display");
         wl::wl_display_run(server.wl_display);
 
+        // event source must go before the event loop does
+        server.ipc = None;
+
         wl::wl_display_destroy_clients(server.wl_display);
         wl::wl_display_destroy(server.wl_display);
     }
@@ -151,6 +246,38 @@ unsafe fn begin_adventure() {
 
 // ---
 
+/// Start rspwlrc. It's an executable (typically a shell script) calling rspc, like bspwmrc.
+pub fn run_config(server: &mut Server) {
+    server.apply_settings();
+
+    let config_home = std::env::var("XDG_CONFIG_HOME")
+        .ok()
+        .or_else(|| std::env::var("HOME").ok().map(|home| format!("{}/.config", home)));
+    let path = config_home.map(|dir| format!("{}/rspwl/rspwlrc", dir));
+
+    match path {
+        Some(path) if std::path::Path::new(&path).exists() => {
+            println!("Run {}", path);
+            let result = std::process::Command::new(&path)
+                .env("WAYLAND_DISPLAY", &server.wayland_display_name)
+                .spawn();
+            if let Err(e) = result {
+                eprintln!("Failed to run {}: {}", path, e);
+            }
+        }
+        _ => {
+            // Something to get started with
+            println!("No rspwlrc, using default key bindings");
+            for (combo, command) in DEFAULT_BINDINGS {
+                let command = format!("bind {} {}", combo, command);
+                if let Err(e) = server.run_command_line(&command) {
+                    eprintln!("Default binding {} failed: {}", combo, e);
+                }
+            }
+        }
+    }
+}
+
 fn new_output(server: &mut Server, wlr_output: &mut wl::wlr_output, _: ()) {
     unsafe {
         wl::wlr_output_init_render(wlr_output, server.allocator, server.renderer);
@@ -195,12 +322,13 @@ fn new_xdg_surface(server: &mut Server, xdg_surface: &mut wl::wlr_xdg_surface, _
         return;
     }
 
-    server
-        .wm
-        .add_view(|id| unsafe { View::from_xdg_toplevel_surface(id, xdg_surface) });
+    // Placed when it maps, app_id and title are known by then
+    server.wm.add_view(|id| unsafe { View::from_xdg_toplevel_surface(id, xdg_surface) });
 
-    // TODO remove this
-    server.invalidate_everything();
+    // Initial configure without a size, so the client tells what size it wants
+    unsafe {
+        wl::wlr_xdg_surface_schedule_configure(xdg_surface);
+    }
 }
 
 unsafe extern "C" fn damage_handle_frame(listener: *mut wl::wl_listener, _: *mut c_void) {
@@ -302,8 +430,6 @@ unsafe fn render_surface(
         let mut nrects = 0;
         let rects = wl::pixman_region32_rectangles(&mut damage, &mut nrects);
         let opacity = 1.0;
-        let mut ow = 0;
-        let mut oh = 0;
 
         for idx in 0..nrects {
             let rect = &*rects.offset(idx as isize);
@@ -318,12 +444,7 @@ unsafe fn render_surface(
                 height: rect.y2 - rect.y1,
             };
             println!("- RECT {:?}", scissor_box);
-
-            wl::wlr_output_transformed_resolution(output.wlr_output, &mut ow, &mut oh);
-            let transform = wl::wlr_output_transform_invert((*output.wlr_output).transform);
-            wl::wlr_box_transform(&mut scissor_box, &mut scissor_box, transform, ow, oh);
-
-            wl::wlr_renderer_scissor(server.renderer, &mut scissor_box);
+            scissor(server, output, &mut scissor_box);
 
             // In addition to this, Sway would configure opengl texture scaling filter. We might
             // want to do that as well.
@@ -367,6 +488,83 @@ unsafe fn render_surface(
     wl::wlr_surface_send_frame_done(surface, when);
 }
 
+/// Only render inside the box, which is in output coordinates
+unsafe fn scissor(server: &Server, output: &Output, scissor_box: &mut wl::wlr_box) {
+    let mut ow = 0;
+    let mut oh = 0;
+    wl::wlr_output_transformed_resolution(output.wlr_output, &mut ow, &mut oh);
+    let transform = wl::wlr_output_transform_invert((*output.wlr_output).transform);
+    wl::wlr_box_transform(scissor_box, scissor_box, transform, ow, oh);
+
+    wl::wlr_renderer_scissor(server.renderer, scissor_box);
+}
+
+/// Border of the given width around the rect, where the output is damaged
+unsafe fn render_border(
+    server: &Server,
+    output: &Output,
+    rect: &Rect,
+    width: f32,
+    color: &Color,
+    output_damage: *mut wl::pixman_region32,
+) {
+    let o = output_coords(server.output_layout, output);
+    let (x, y) = (rect.x as f64 + o.x, rect.y as f64 + o.y);
+    let (w, h, bw) = (rect.w as f64, rect.h as f64, width as f64);
+    let sides = [
+        scaled_box(output, x - bw, y - bw, w + 2.0 * bw, bw),
+        scaled_box(output, x - bw, y + h, w + 2.0 * bw, bw),
+        scaled_box(output, x - bw, y, bw, h),
+        scaled_box(output, x + w, y, bw, h),
+    ];
+
+    for side in sides.iter() {
+        render_rect(server, output, side, color, output_damage);
+    }
+}
+
+/// Fill the box, which is in output coordinates, where the output is damaged
+unsafe fn render_rect(
+    server: &Server,
+    output: &Output,
+    rect: &wl::wlr_box,
+    color: &Color,
+    output_damage: *mut wl::pixman_region32,
+) {
+    let mut damage = MaybeUninit::uninit();
+    wl::pixman_region32_init(damage.as_mut_ptr());
+    let mut damage = damage.assume_init();
+    wl::pixman_region32_union_rect(
+        &mut damage,
+        &mut damage,
+        rect.x,
+        rect.y,
+        rect.width as u32,
+        rect.height as u32,
+    );
+    wl::pixman_region32_intersect(&mut damage, &mut damage, output_damage);
+
+    let mut nrects = 0;
+    let rects = wl::pixman_region32_rectangles(&mut damage, &mut nrects);
+    for idx in 0..nrects {
+        let r = &*rects.offset(idx as isize);
+        let mut scissor_box = wl::wlr_box {
+            x: r.x1,
+            y: r.y1,
+            width: r.x2 - r.x1,
+            height: r.y2 - r.y1,
+        };
+        scissor(server, output, &mut scissor_box);
+        wl::wlr_render_rect(
+            server.renderer,
+            rect,
+            color.0.as_ptr(),
+            (*output.wlr_output).transform_matrix.as_ptr(),
+        );
+    }
+    wl::pixman_region32_fini(&mut damage);
+}
+
 unsafe fn render(output: &mut Output, damage: *mut wl::pixman_region32) {
     let server = &*server_ptr();
     let renderer = server.renderer;
@@ -383,9 +581,31 @@ unsafe fn render(output: &mut Output, damage: *mut wl::pixman_region32) {
     //let color = [0.3, 0.3, 0.3, 1.0];
     //wl::wlr_renderer_clear(renderer, color.as_ptr());
 
+    // Active window of the output is the focused one if the output has the focus
+    let active = server
+        .wm
+        .output_workspace(output.id)
+        .and_then(|workspace| server.wm.active_window(workspace));
+    let active_color = if server.wm.focused_output() == Some(output.id) {
+        &server.settings.focused_border_color
+    } else {
+        &server.settings.active_border_color
+    };
+
     // println!("BEGIN RENDER");
     for x in server.wm.views_for_render(output.id) {
         let (view, rect) = x.content_and_rect();
+        let window = x.window();
+        if window.border_width > 0.0 {
+            let color = if window.urgent {
+                &server.settings.urgent_border_color
+            } else if active == Some(view.id) {
+                active_color
+            } else {
+                &server.settings.normal_border_color
+            };
+            render_border(server, output, rect, window.border_width, color, damage);
+        }
         match &view.shell_surface {
             ShellView::Empty => println!("Empty view (WHY???)"),
             ShellView::Xdg(xdgview) => {
@@ -400,6 +620,21 @@ unsafe fn render(output: &mut Output, damage: *mut wl::pixman_region32) {
         //     Some(render_surface),
         //     &mut rdata as *mut RenderData as *mut c_void,
         // );
+        // Area the next window would take, over the window
+        if server.settings.presel_feedback {
+            if let Some(area) = server.wm.presel_rect(view.id) {
+                let o = output_coords(server.output_layout, output);
+                let area = scaled_box(
+                    output,
+                    area.x as f64 + o.x,
+                    area.y as f64 + o.y,
+                    area.w as f64,
+                    area.h as f64,
+                );
+                let color = &server.settings.presel_feedback_color;
+                render_rect(server, output, &area, color, damage);
+            }
+        }
     }
     wl::wlr_renderer_scissor(server.renderer, ptr::null_mut());
     wl::wlr_output_render_software_cursors(output.wlr_output, ptr::null_mut());
@@ -415,7 +650,18 @@ fn output_destroy(server: &mut Server, _: &mut (), id: OutputId) {
 
 fn cursor_motion(server: &mut Server, motion: &mut wl::wlr_event_pointer_motion, _: ()) {
     unsafe {
-        wl::wlr_cursor_move(server.cursor, motion.device, motion.delta_x, motion.delta_y);
+        // Clients that want it get the motion even when the pointer is locked
+        wl::wlr_relative_pointer_manager_v1_send_relative_motion(
+            server.relative_pointer_manager,
+            server.seat,
+            motion.time_msec as u64 * 1000,
+            motion.delta_x,
+            motion.delta_y,
+            motion.unaccel_dx,
+            motion.unaccel_dy,
+        );
+        let (dx, dy) = server.constrain_motion(motion.delta_x, motion.delta_y);
+        wl::wlr_cursor_move(server.cursor, motion.device, dx, dy);
         handle_cursor_move(server, motion.time_msec);
     }
 }
@@ -425,12 +671,23 @@ fn cursor_motion_absolute(
     _: (),
 ) {
     unsafe {
-        wl::wlr_cursor_warp_absolute(server.cursor, motion.device, motion.x, motion.y);
+        let mut to = Point { x: 0.0, y: 0.0 };
+        wl::wlr_cursor_absolute_to_layout_coords(
+            server.cursor,
+            motion.device,
+            motion.x,
+            motion.y,
+            &mut to.x,
+            &mut to.y,
+        );
+        let pos = cursor_pos(server);
+        let (dx, dy) = server.constrain_motion(to.x - pos.x, to.y - pos.y);
+        wl::wlr_cursor_move(server.cursor, motion.device, dx, dy);
         handle_cursor_move(server, motion.time_msec);
     }
 }
 
-fn cursor_pos(server: &Server) -> Point {
+pub fn cursor_pos(server: &Server) -> Point {
     unsafe {
         Point {
             x: (*server.cursor).x,
@@ -438,12 +695,21 @@ fn cursor_pos(server: &Server) -> Point {
         }
     }
 }
-unsafe fn handle_cursor_move(server: &mut Server, time: u32) {
+pub unsafe fn handle_cursor_move(server: &mut Server, time: u32) {
+    if update_pointer_focus(server, time) && server.settings.focus_follows_pointer {
+        focus_follows_pointer(server);
+    }
+}
+
+/// Give the pointer to the surface under it, also when the windows moved instead of the pointer.
+/// Returns if there is a window under it.
+pub unsafe fn update_pointer_focus(server: &mut Server, time: u32) -> bool {
     if let Some((_, surface, p)) = find_window(server, cursor_pos(server)) {
         // Enter is kind of wrong after the first time, but wlroots promises to disregard those so
         // no matter
         wl::wlr_seat_pointer_notify_enter(server.seat, surface, p.x, p.y);
         wl::wlr_seat_pointer_notify_motion(server.seat, time, p.x, p.y);
+        true
     } else {
         // TODO Could figure out if it's ok to just call this all the time
         wl::wlr_xcursor_manager_set_cursor_image(
@@ -453,41 +719,138 @@ unsafe fn handle_cursor_move(server: &mut Server, time: u32) {
         );
 
         wl::wlr_seat_pointer_clear_focus(server.seat);
+        false
+    }
+}
+
+/// Focus changes at most this often when the pointer goes over windows
+const FOCUS_FOLLOWS_POINTER_INTERVAL: Duration = Duration::from_millis(100);
+
+fn focus_follows_pointer(server: &mut Server) {
+    let now = Instant::now();
+    let recently = server
+        .pointer_focus_time
+        .map_or(false, |t| now.duration_since(t) < FOCUS_FOLLOWS_POINTER_INTERVAL);
+    if recently {
+        return;
+    }
+
+    let Some((viewref, _, _)) = find_window(server, cursor_pos(server)) else {
+        return;
+    };
+    let id = viewref.content_and_rect().0.id;
+    std::mem::drop(viewref);
+    if server.wm.active_node().map(|n| n.id) != Some(id) {
+        server.focus_node(id);
+        server.pointer_focus_time = Some(now);
     }
 }
 
 fn cursor_button(server: &mut Server, event: &mut wl::wlr_event_pointer_button, _: ()) {
+    let pressed = event.state == wl::wlr_button_state_WLR_BUTTON_PRESSED;
+
+    let action = if pressed {
+        server
+            .bindings
+            .press_button(event.button, keyboard_modifiers(server))
+    } else {
+        server.bindings.release_button(event.button)
+    };
+    match action {
+        KeyAction::Pass => (),
+        KeyAction::Swallow => return,
+        KeyAction::Run(command) => {
+            println!("Button binding: {}", command);
+            if let Err(e) = server.run_command_line(&command) {
+                println!("Button binding failed: {}", e);
+            }
+            return;
+        }
+    }
+
+    if pressed && server.settings.click_to_focus.focuses(event.button) {
+        let focused = focus_clicked(server);
+        if focused && server.settings.swallow_first_click {
+            server.bindings.hold_button(event.button);
+            return;
+        }
+    }
+
     unsafe {
         wl::wlr_seat_pointer_notify_button(server.seat, event.time_msec, event.button, event.state);
     }
+}
 
-    if event.state == wl::wlr_button_state_WLR_BUTTON_RELEASED {
-        // TODO release button here (resize drag, etc)
-    } else {
-        let to_focus = if let Some((viewref, surface, _)) = find_window(server, cursor_pos(server)) {
-            let view = viewref.content_and_rect().0;
-            let focus_toplevel = match &view.shell_surface {
-                ShellView::Xdg(v) => Some(v.xdgsurface.xdg_surface),
-                ShellView::Empty => None,
-                // NOTE: doesn't really work for non xdg, but that's a problem for another day
-            };
-            println!("clicked view {}", view.id);
-            let view_id = view.id;
+/// Focus the window under the pointer. Tells if the focus changed.
+fn focus_clicked(server: &mut Server) -> bool {
+    focus_at(server, cursor_pos(server))
+}
 
-            focus_toplevel.map(|x| (view_id, surface, x))
-        } else {
-            println!("clicked outside view");
-            None
+pub fn focus_at(server: &mut Server, pos: Point) -> bool {
+    let to_focus = if let Some((viewref, surface, _)) = find_window(server, pos) {
+        let view = viewref.content_and_rect().0;
+        let focus_toplevel = match &view.shell_surface {
+            ShellView::Xdg(v) => Some(v.xdgsurface.xdg_surface),
+            ShellView::Empty => None,
+            // NOTE: doesn't really work for non xdg, but that's a problem for another day
         };
+        println!("clicked view {}", view.id);
+        let view_id = view.id;
 
-        if let Some((view_id, surface, xdgsurface)) = to_focus {
-            unsafe {
-                server.focus_view(xdgsurface, view_id, surface);
-            }
+        focus_toplevel.map(|x| (view_id, surface, x))
+    } else {
+        println!("clicked outside view");
+        None
+    };
+
+    match to_focus {
+        Some((view_id, surface, xdgsurface)) => unsafe {
+            let was_focused = server.wm.active_node().map(|n| n.id) == Some(view_id)
+                && !(*server.seat).keyboard_state.focused_surface.is_null();
+            server.focus_view(xdgsurface, view_id, surface);
+            !was_focused
+        },
+        None => false,
+    }
+}
+
+pub fn keyboard_modifiers(server: &Server) -> u32 {
+    unsafe {
+        let keyboard = wl::wlr_seat_get_keyboard(server.seat);
+        if keyboard.is_null() {
+            0
+        } else {
+            wl::wlr_keyboard_get_modifiers(keyboard)
         }
     }
 }
+
 fn cursor_axis(server: &mut Server, event: &mut wl::wlr_event_pointer_axis, _: ()) {
+    let vertical = event.orientation == wl::wlr_axis_orientation_WLR_AXIS_ORIENTATION_VERTICAL;
+    let dir = match (vertical, event.delta < 0.0) {
+        (true, true) => ScrollDir::Up,
+        (true, false) => ScrollDir::Down,
+        (false, true) => ScrollDir::Left,
+        (false, false) => ScrollDir::Right,
+    };
+    // Zero is the axis stop at the end of a finger scroll, it has no direction
+    let bound = if event.delta == 0.0 {
+        None
+    } else {
+        server.bindings.scroll(dir, keyboard_modifiers(server))
+    };
+    if let Some(command) = bound {
+        // Only wheel clicks run it, smooth scrolling would run it way too often. Bound scrolling
+        // isn't given to the client either way.
+        if event.delta_discrete != 0 {
+            println!("Scroll binding: {}", command);
+            if let Err(e) = server.run_command_line(&command) {
+                println!("Scroll binding failed: {}", e);
+            }
+        }
+        return;
+    }
+
     // mouse wheel
     unsafe {
         wl::wlr_seat_pointer_notify_axis(
@@ -512,11 +875,11 @@ fn handle_new_input(server: &mut Server, device: &mut wl::wlr_input_device, _: (
     if device.type_ == wl::wlr_input_device_type_WLR_INPUT_DEVICE_KEYBOARD {
         let id = (1..255).find(|i| server.keyboards.iter().all(|x| x.id != *i));
         if let Some(id) = id {
-            // TODO keyboard destroy not handled??
             let mut keyboard = Box::pin(Keyboard {
                 device,
                 modifiers: Listener::new(handle_modifiers, id),
                 key: Listener::new(handle_key, id),
+                destroy: Listener::new(keyboard_destroy, id),
                 id,
             });
             unsafe {
@@ -525,20 +888,12 @@ fn handle_new_input(server: &mut Server, device: &mut wl::wlr_input_device, _: (
                 let events = &mut wlr_keyboard.events;
                 listen_server_signal(&mut events.modifiers, &mut kb.modifiers);
                 listen_server_signal(&mut events.key, &mut kb.key);
+                listen_server_signal(&mut device.events.destroy, &mut kb.destroy);
 
-                let context = wl::xkb_context_new(wl::xkb_context_flags_XKB_CONTEXT_NO_FLAGS);
-                let keymap = wl::xkb_keymap_new_from_names(
-                    context,
-                    ptr::null(),
-                    wl::xkb_keymap_compile_flags_XKB_KEYMAP_COMPILE_NO_FLAGS,
-                );
-
-                // TODO: these should actually come from some kind of keyboard settings (and not
-                // hard coded)
-                wl::wlr_keyboard_set_keymap(wlr_keyboard, keymap);
-                wl::xkb_keymap_unref(keymap);
-                wl::xkb_context_unref(context);
-                wl::wlr_keyboard_set_repeat_info(wlr_keyboard, 25, 600);
+                if let Err(e) = server.configure_keyboard(device) {
+                    eprintln!("{}, using the default keymap", e);
+                    set_keymap(wlr_keyboard, ptr::null());
+                }
 
                 // set the keyboard in here as well
                 println!("Set keybrd");
@@ -551,15 +906,115 @@ fn handle_new_input(server: &mut Server, device: &mut wl::wlr_input_device, _: (
             eprintln!("Can't add keyboard. WHY DO YOU HAVE SO MANY KEYBOARDS?");
         }
     } else if device.type_ == wl::wlr_input_device_type_WLR_INPUT_DEVICE_POINTER {
-        unsafe {
-            wl::wlr_cursor_attach_input_device(server.cursor, device);
+        let id = (1..255).find(|i| server.pointers.iter().all(|x| x.id != *i));
+        if let Some(id) = id {
+            let mut pointer = Box::pin(Pointer {
+                device,
+                destroy: Listener::new(pointer_destroy, id),
+                id,
+            });
+            unsafe {
+                let p = pointer.as_mut().get_unchecked_mut();
+                listen_server_signal(&mut device.events.destroy, &mut p.destroy);
+
+                if let Err(e) = server.configure_pointer(device) {
+                    eprintln!("{}", e);
+                }
+                wl::wlr_cursor_attach_input_device(server.cursor, device);
+            }
+            println!("Add pointer");
+            server.pointers.push(pointer);
+        } else {
+            eprintln!("Can't add pointer, too many of them");
         }
+    } else if device.type_ == wl::wlr_input_device_type_WLR_INPUT_DEVICE_TOUCH {
+        let id = (1..255).find(|i| server.touch_devices.iter().all(|x| x.id != *i));
+        if let Some(id) = id {
+            let mut touch = Box::pin(Pointer {
+                device,
+                destroy: Listener::new(touch_destroy, id),
+                id,
+            });
+            unsafe {
+                let t = touch.as_mut().get_unchecked_mut();
+                listen_server_signal(&mut device.events.destroy, &mut t.destroy);
+                wl::wlr_cursor_attach_input_device(server.cursor, device);
+            }
+            println!("Add touch");
+            server.touch_devices.push(touch);
+            server.map_to_outputs();
+        } else {
+            eprintln!("Can't add touch device, too many of them");
+        }
+    } else if device.type_ == wl::wlr_input_device_type_WLR_INPUT_DEVICE_TABLET_TOOL {
+        unsafe { server.add_tablet(device) };
+    } else if device.type_ == wl::wlr_input_device_type_WLR_INPUT_DEVICE_TABLET_PAD {
+        unsafe { server.add_tablet_pad(device) };
+    }
+
+    update_seat_capabilities(server);
+}
+
+fn keyboard_destroy(server: &mut Server, _: &mut (), id: KeyboardId) {
+    let Some(i) = server.keyboards.iter().position(|x| x.id == id) else {
+        return;
+    };
+    // Dropping it removes the listeners
+    let keyboard = server.keyboards.remove(i);
+    println!("Remove keyboard");
+
+    unsafe {
+        // Seat may have forgotten it already
+        let current = wl::wlr_seat_get_keyboard(server.seat);
+        if current.is_null() || current == (*keyboard.device).__bindgen_anon_1.keyboard {
+            let next = server
+                .keyboards
+                .last()
+                .map_or(ptr::null_mut(), |kb| kb.device);
+            wl::wlr_seat_set_keyboard(server.seat, next);
+        }
+    }
+    update_seat_capabilities(server);
+}
+
+fn pointer_destroy(server: &mut Server, _: &mut (), id: PointerId) {
+    let Some(i) = server.pointers.iter().position(|x| x.id == id) else {
+        return;
+    };
+    let pointer = server.pointers.remove(i);
+    println!("Remove pointer");
+
+    unsafe {
+        wl::wlr_cursor_detach_input_device(server.cursor, pointer.device);
+    }
+    update_seat_capabilities(server);
+}
+
+fn touch_destroy(server: &mut Server, _: &mut (), id: PointerId) {
+    let Some(i) = server.touch_devices.iter().position(|x| x.id == id) else {
+        return;
+    };
+    let touch = server.touch_devices.remove(i);
+    println!("Remove touch");
+
+    unsafe {
+        wl::wlr_cursor_detach_input_device(server.cursor, touch.device);
     }
+    update_seat_capabilities(server);
+}
 
-    // why wouldn't you have all the caps
-    // TODO could still do this right
-    let caps = wl::wl_seat_capability_WL_SEAT_CAPABILITY_POINTER
-        | wl::wl_seat_capability_WL_SEAT_CAPABILITY_KEYBOARD;
+/// Clients see the seat has a pointer, keyboard or touch only when there is one
+fn update_seat_capabilities(server: &Server) {
+    let mut caps = 0;
+    if !server.pointers.is_empty() {
+        caps |= wl::wl_seat_capability_WL_SEAT_CAPABILITY_POINTER;
+    }
+    if !server.keyboards.is_empty() {
+        caps |= wl::wl_seat_capability_WL_SEAT_CAPABILITY_KEYBOARD;
+    }
+    if !server.touch_devices.is_empty() {
+        caps |= wl::wl_seat_capability_WL_SEAT_CAPABILITY_TOUCH;
+    }
 
     unsafe {
         wl::wlr_seat_set_capabilities(server.seat, caps);
@@ -619,42 +1074,68 @@ fn handle_key(server: &mut Server, event: &mut wl::wlr_event_keyboard_key, id: u
         return;
     };
 
-    let mut keybind_handled = false;
-    if event.state == wl::wl_keyboard_key_state_WL_KEYBOARD_KEY_STATE_PRESSED {
+    let keycode = event.keycode + 8;
+    let action = if event.state == wl::wl_keyboard_key_state_WL_KEYBOARD_KEY_STATE_PRESSED {
         unsafe {
             wl::wlr_seat_set_keyboard(server.seat, keyboard.device);
-            let device_keyboard = (*keyboard.device).__bindgen_anon_1.keyboard;
-            let modifiers = wl::wlr_keyboard_get_modifiers(device_keyboard);
-            let mut syms: *const wl::xkb_keysym_t = ptr::null_mut();
-            let nsyms = wl::xkb_state_key_get_syms(
-                (*device_keyboard).xkb_state,
-                event.keycode + 8,
-                &mut syms as *mut *const wl::xkb_keysym_t,
-            );
-            for index in 0..nsyms {
-                let sym = syms.offset(index as isize);
-                keybind_handled = server.handle_key_binding(*sym, modifiers) || keybind_handled;
-            }
+            let candidates = key_candidates((*keyboard.device).__bindgen_anon_1.keyboard, keycode);
+            server.bindings.press(keycode, &candidates, Instant::now())
         }
-    }
-
-    if keybind_handled {
-        println!("Handled key binding");
     } else {
-        // pass it to the seat
-        unsafe {
+        server.bindings.release(keycode)
+    };
+
+    match action {
+        KeyAction::Pass => unsafe {
+            // pass it to the seat
             wl::wlr_seat_keyboard_notify_key(
                 server.seat,
                 event.time_msec,
                 event.keycode,
                 event.state,
             );
+        },
+        KeyAction::Swallow => println!("Key {} is for a binding", keycode),
+        KeyAction::Run(command) => {
+            println!("Key binding: {}", command);
+            if let Err(e) = server.run_command_line(&command) {
+                println!("Key binding failed: {}", e);
+            }
         }
     }
 }
 
+/// (modifiers, keysym) pairs the key could mean for bindings. Keysyms without modifiers applied
+/// (alt+shift+h), and the ones modifiers gave with those modifiers left out (alt+H). The wlr
+/// modifier bits are the same as the core xkb ones.
+unsafe fn key_candidates(keyboard: *mut wl::wlr_keyboard, keycode: u32) -> Vec<(u32, u32)> {
+    let modifiers = wl::wlr_keyboard_get_modifiers(keyboard);
+    let state = (*keyboard).xkb_state;
+    let mut candidates = Vec::new();
+
+    let mut syms: *const wl::xkb_keysym_t = ptr::null();
+    let layout = wl::xkb_state_key_get_layout(state, keycode);
+    let nsyms =
+        wl::xkb_keymap_key_get_syms_by_level((*keyboard).keymap, keycode, layout, 0, &mut syms);
+    for index in 0..nsyms {
+        candidates.push((modifiers, *syms.offset(index as isize)));
+    }
+
+    let consumed = wl::xkb_state_key_get_consumed_mods2(
+        state,
+        keycode,
+        wl::xkb_consumed_mode_XKB_CONSUMED_MODE_XKB,
+    );
+    let nsyms = wl::xkb_state_key_get_syms(state, keycode, &mut syms);
+    for index in 0..nsyms {
+        candidates.push((modifiers & !consumed, *syms.offset(index as isize)));
+    }
+
+    candidates
+}
+
 // TODO move?
-fn find_window<'a>(
+pub fn find_window<'a>(
     server: &'a Server,
     pos: Point,
 ) -> Option<(ViewRef<'a>, *mut wl::wlr_surface, Point)> {
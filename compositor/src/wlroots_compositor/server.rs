@@ -3,17 +3,29 @@ use std::marker::PhantomPinned;
 use std::mem::MaybeUninit;
 use std::pin::Pin;
 use std::ptr;
+use std::time::Instant;
 
 use wl_sys as wl;
 
-use crate::tree::{Direction, self};
+use crate::bindings::Bindings;
+use crate::input_config::InputConfig;
+use crate::rules::Rules;
+use crate::settings::{FocusOnClose, Settings};
 use crate::types::{NodeId, Rect};
-use crate::window_manager::{OutputInfo, WindowManager};
-
+use crate::window_manager::{Flag, OutputInfo, WindowManager, WindowState};
+
+use super::constraints::Constraint;
+use super::gestures::BoundSwipe;
+use super::ipc::Ipc;
+use super::placement::PendingRules;
+use super::runner::update_pointer_focus;
+use super::tablet::{Tablet, TabletPad, TabletTool};
+use super::touch::TouchPoint;
 use super::wl_util::*;
 
 pub type OutputId = u8;
 pub type KeyboardId = u8;
+pub type PointerId = u8;
 
 static mut SERVER_GLOBAL: *mut Server = ptr::null_mut();
 
@@ -44,12 +56,49 @@ pub struct Server {
     pub cursor_button: Listener<wl::wlr_event_pointer_button, ()>,
     pub cursor_axis: Listener<wl::wlr_event_pointer_axis, ()>,
     pub cursor_frame: Listener<(), ()>,
+    pub cursor_touch_down: Listener<wl::wlr_event_touch_down, ()>,
+    pub cursor_touch_up: Listener<wl::wlr_event_touch_up, ()>,
+    pub cursor_touch_motion: Listener<wl::wlr_event_touch_motion, ()>,
+    pub cursor_touch_cancel: Listener<wl::wlr_event_touch_cancel, ()>,
+    pub cursor_touch_frame: Listener<(), ()>,
+    pub cursor_tool_axis: Listener<wl::wlr_event_tablet_tool_axis, ()>,
+    pub cursor_tool_proximity: Listener<wl::wlr_event_tablet_tool_proximity, ()>,
+    pub cursor_tool_tip: Listener<wl::wlr_event_tablet_tool_tip, ()>,
+    pub cursor_tool_button: Listener<wl::wlr_event_tablet_tool_button, ()>,
+    pub cursor_swipe_begin: Listener<wl::wlr_event_pointer_swipe_begin, ()>,
+    pub cursor_swipe_update: Listener<wl::wlr_event_pointer_swipe_update, ()>,
+    pub cursor_swipe_end: Listener<wl::wlr_event_pointer_swipe_end, ()>,
+    pub cursor_pinch_begin: Listener<wl::wlr_event_pointer_pinch_begin, ()>,
+    pub cursor_pinch_update: Listener<wl::wlr_event_pointer_pinch_update, ()>,
+    pub cursor_pinch_end: Listener<wl::wlr_event_pointer_pinch_end, ()>,
+    pub cursor_hold_begin: Listener<wl::wlr_event_pointer_hold_begin, ()>,
+    pub cursor_hold_end: Listener<wl::wlr_event_pointer_hold_end, ()>,
+    pub pointer_gestures: *mut wl::wlr_pointer_gestures_v1,
+    pub relative_pointer_manager: *mut wl::wlr_relative_pointer_manager_v1,
+    pub pointer_constraints: *mut wl::wlr_pointer_constraints_v1,
+    pub new_constraint: Listener<wl::wlr_pointer_constraint_v1, ()>,
+    pub constraints: Vec<Pin<Box<Constraint>>>,
+    /// Null when there's none
+    pub active_constraint: *mut wl::wlr_pointer_constraint_v1,
+    /// Last time focus_follows_pointer changed the focus
+    pub pointer_focus_time: Option<Instant>,
+    pub swipe: Option<BoundSwipe>,
 
     pub seat: *mut wl::wlr_seat,
     pub new_input: Listener<wl::wlr_input_device, ()>,
     pub request_cursor: Listener<wl::wlr_seat_pointer_request_set_cursor_event, ()>,
     pub request_set_selection: Listener<wl::wlr_seat_request_set_selection_event, ()>,
     pub keyboards: Vec<Pin<Box<Keyboard>>>,
+    pub pointers: Vec<Pin<Box<Pointer>>>,
+    /// Touchscreens, same as pointers for us
+    pub touch_devices: Vec<Pin<Box<Pointer>>>,
+    pub touch_points: Vec<TouchPoint>,
+    /// Touch that is moving the pointer for a client that doesn't do touch
+    pub touch_emulation: Option<i32>,
+    pub tablet_manager: *mut wl::wlr_tablet_manager_v2,
+    pub tablets: Vec<Pin<Box<Tablet>>>,
+    pub tablet_pads: Vec<Pin<Box<TabletPad>>>,
+    pub tablet_tools: Vec<Pin<Box<TabletTool>>>,
 
     pub output_layout: *mut wl::wlr_output_layout,
     pub outputs: Vec<Pin<Box<Output>>>,
@@ -57,6 +106,14 @@ pub struct Server {
     pub new_xdg_surface: Listener<wl::wlr_xdg_surface, ()>,
     pub new_output: Listener<wl::wlr_output, ()>,
 
+    pub ipc: Option<Ipc>,
+    pub settings: Settings,
+    pub rules: Rules,
+    /// Windows not placed until external_rules_command is done
+    pub pending_rules: Vec<PendingRules>,
+    pub bindings: Bindings,
+    pub input_config: InputConfig,
+
     pub wm: WindowManager,
 }
 
@@ -70,11 +127,15 @@ impl Server {
     pub fn add_output(&mut self, output: Pin<Box<Output>>) {
         self.outputs.push(output);
         self.update_wm_outputs();
+        self.map_to_outputs();
+        self.layout_changed();
     }
 
     pub fn remove_output(&mut self, output_id: OutputId) {
         self.outputs.retain(|x| x.id != output_id);
         self.update_wm_outputs();
+        self.map_to_outputs();
+        self.layout_changed();
     }
 
     pub fn update_wm_outputs(&mut self) {
@@ -97,78 +158,81 @@ impl Server {
         }));
     }
 
-    pub fn handle_key_binding(&mut self, keysym: u32, modifiers: u32) -> bool {
-        // Just some hardcoded keys for now
+    /// Find the toplevel view of xdg surface
+    pub fn find_xdg_view(&self, xdg_surface: *mut wl::wlr_xdg_surface) -> Option<NodeId> {
+        self.wm.views().find_map(|viewref| {
+            let (view, _) = viewref.content_and_rect();
+            match &view.shell_surface {
+                ShellView::Xdg(xdg) if xdg.xdgsurface.xdg_surface == xdg_surface => Some(view.id),
+                _ => None,
+            }
+        })
+    }
 
-        let is_alt = modifiers & wl::wlr_keyboard_modifier_WLR_MODIFIER_ALT != 0;
+    pub fn focus_node(&mut self, id: NodeId) {
+        println!("activate {}", id);
+        let Some(viewref) = self.wm.find_view(id) else { return; };
+        let (view, _) = viewref.content_and_rect();
+        match &view.shell_surface {
+            ShellView::Empty => (),
+            ShellView::Xdg(xdg) => unsafe {
+                let toplevel = xdg.xdgsurface.xdg_surface;
+                std::mem::drop(viewref);
+                self.focus_view(toplevel, id, (*toplevel).surface)
+            },
+        }
+    }
 
-        fn handle_hjkl(server: &mut Server, dir: Direction, swap: bool) {
-            let Some(neighbor) = server.wm.neighbor(dir) else { return; };
-            if swap {
-                if let Some(active) = server.wm.active_node() {
-                    println!("swap {} {}", active.id, neighbor.id);
-                    tree::swap(active, neighbor);
-                    server.invalidate_everything();
-                }
-            } else {
-                println!("activate {}", neighbor.id);
-                let viewref = server.wm.find_view(neighbor.id).unwrap();
-                let (view, _) = viewref.content_and_rect();
-                match &view.shell_surface {
-                    ShellView::Empty => (),
-                    ShellView::Xdg(xdg) => unsafe {
-                        let toplevel = xdg.xdgsurface.xdg_surface;
-                        std::mem::drop(viewref);
-                        server.focus_view(toplevel, neighbor.id, ptr::null_mut())
-                    },
-                }
-            }
-            server.wm.configure_views();
+    unsafe fn handle_fullscreen_request(
+        &mut self,
+        xdg_surface: *mut wl::wlr_xdg_surface,
+        view_id: NodeId,
+        fullscreen: bool,
+    ) {
+        let result = if self.settings.ignore_fullscreen_requests.ignores(fullscreen) {
+            println!("Ignored fullscreen request from {}", view_id);
+            Ok(())
+        } else if fullscreen {
+            self.wm.set_state(view_id, WindowState::Fullscreen)
+        } else {
+            self.wm.unset_state(view_id, WindowState::Fullscreen)
+        };
+        if let Err(e) = result {
+            println!("Fullscreen request failed: {}", e);
         }
 
-        if is_alt {
-            dbg!(keysym);
-            if keysym == wl::XKB_KEY_Return {
-                println!("Spawn shell");
-                let ok = std::process::Command::new("foot")
-                    .env("WAYLAND_DISPLAY", &self.wayland_display_name)
-                    .spawn()
-                    .is_ok();
-
-                if !ok {
-                    println!("Failed to start foot");
-                }
-                true
-            } else if keysym == wl::XKB_KEY_h {
-                handle_hjkl(self, Direction::Left, false);
-                true
-            } else if keysym == wl::XKB_KEY_j {
-                handle_hjkl(self, Direction::Down, false);
-                true
-            } else if keysym == wl::XKB_KEY_k {
-                handle_hjkl(self, Direction::Up, false);
-                true
-            } else if keysym == wl::XKB_KEY_l {
-                handle_hjkl(self, Direction::Right, false);
-                true
-            } else if keysym == wl::XKB_KEY_H {
-                handle_hjkl(self, Direction::Left, true);
-                true
-            } else if keysym == wl::XKB_KEY_J {
-                handle_hjkl(self, Direction::Down, true);
-                true
-            } else if keysym == wl::XKB_KEY_K {
-                handle_hjkl(self, Direction::Up, true);
-                true
-            } else if keysym == wl::XKB_KEY_L {
-                handle_hjkl(self, Direction::Right, true);
-                true
-            } else {
-                false
-            }
+        // Client must get a configure even if nothing changed
+        wl::wlr_xdg_surface_schedule_configure(xdg_surface);
+        self.layout_changed();
+    }
+
+    unsafe fn handle_maximize_request(
+        &mut self,
+        xdg_surface: *mut wl::wlr_xdg_surface,
+        view_id: NodeId,
+        maximized: bool,
+    ) {
+        let result = if self.settings.ignore_maximize_requests.ignores(maximized) {
+            println!("Ignored maximize request from {}", view_id);
+            Ok(())
         } else {
-            false
+            self.wm.set_maximized(view_id, maximized)
+        };
+        if let Err(e) = result {
+            println!("Maximize request failed: {}", e);
+        }
+
+        wl::wlr_xdg_surface_schedule_configure(xdg_surface);
+        self.layout_changed();
+    }
+
+    /// There's nothing to minimize to, so the window is hidden
+    fn handle_minimize_request(&mut self, view_id: NodeId) {
+        if let Err(e) = self.wm.set_flag(view_id, Flag::Hidden, Some(true)) {
+            println!("Minimize request failed: {}", e);
         }
+        self.refocus();
+        self.layout_changed();
     }
 
     pub unsafe fn focus_view(
@@ -192,18 +256,39 @@ impl Server {
             println!("  prev was not xdg surface");
         }
 
-        let keyboard = &mut *wl::wlr_seat_get_keyboard(self.seat);
-
         wl::wlr_xdg_toplevel_set_activated(xdg_surface, true);
-        wl::wlr_seat_keyboard_notify_enter(
-            self.seat,
-            (*xdg_surface).surface,
-            keyboard.keycodes.as_mut_ptr(),
-            keyboard.num_keycodes,
-            &mut keyboard.modifiers,
-        );
+
+        // All keyboards might be unplugged
+        let keyboard = wl::wlr_seat_get_keyboard(self.seat);
+        if keyboard.is_null() {
+            wl::wlr_seat_keyboard_notify_enter(
+                self.seat,
+                (*xdg_surface).surface,
+                ptr::null_mut(),
+                0,
+                ptr::null_mut(),
+            );
+        } else {
+            let keyboard = &mut *keyboard;
+            wl::wlr_seat_keyboard_notify_enter(
+                self.seat,
+                (*xdg_surface).surface,
+                keyboard.keycodes.as_mut_ptr(),
+                keyboard.num_keycodes,
+                &mut keyboard.modifiers,
+            );
+        }
 
         self.wm.touch_node(view_id);
+        // Got the attention it wanted
+        let _ = self.wm.set_flag(view_id, Flag::Urgent, Some(false));
+        self.update_pointer_constraint();
+
+        // Border colors changed
+        self.invalidate_everything();
+
+        // Focused window is raised, it might be under the pointer now
+        update_pointer_focus(self, now_msec());
     }
 
     pub fn invalidate_everything(&self) {
@@ -213,6 +298,12 @@ impl Server {
             }
         }
     }
+
+    /// Windows moved, redraw them and let the pointer enter what is under it now
+    pub fn layout_changed(&mut self) {
+        self.invalidate_everything();
+        unsafe { update_pointer_focus(self, now_msec()) };
+    }
 }
 
 pub struct Output {
@@ -239,9 +330,16 @@ pub struct Keyboard {
     pub device: *mut wl::wlr_input_device,
     pub modifiers: Listener<(), u8>,
     pub key: Listener<wl::wlr_event_keyboard_key, u8>,
+    pub destroy: Listener<(), u8>,
     pub id: KeyboardId,
 }
 
+pub struct Pointer {
+    pub device: *mut wl::wlr_input_device,
+    pub destroy: Listener<(), u8>,
+    pub id: PointerId,
+}
+
 pub struct Listener<Data, Ctx: Copy> {
     listener: ListenerWrapper,
     ctx: Ctx,
@@ -280,11 +378,23 @@ pub enum ShellView {
 }
 
 impl ShellView {
-    fn configure_size(&self, w: u32, h: u32) {
+    fn configure(&self, w: u32, h: u32, state: WindowState, maximized: bool) {
         match self {
             ShellView::Empty => (),
             ShellView::Xdg(xdgview) => unsafe {
-                wl::wlr_xdg_toplevel_set_size(xdgview.xdgsurface.xdg_surface, w, h);
+                let xdg_surface = xdgview.xdgsurface.xdg_surface;
+                let tiled_edges = if state == WindowState::Tiled {
+                    wl::wlr_edges_WLR_EDGE_TOP
+                        | wl::wlr_edges_WLR_EDGE_BOTTOM
+                        | wl::wlr_edges_WLR_EDGE_LEFT
+                        | wl::wlr_edges_WLR_EDGE_RIGHT
+                } else {
+                    wl::wlr_edges_WLR_EDGE_NONE
+                };
+                wl::wlr_xdg_toplevel_set_size(xdg_surface, w, h);
+                wl::wlr_xdg_toplevel_set_tiled(xdg_surface, tiled_edges);
+                wl::wlr_xdg_toplevel_set_fullscreen(xdg_surface, state == WindowState::Fullscreen);
+                wl::wlr_xdg_toplevel_set_maximized(xdg_surface, maximized);
             },
         }
     }
@@ -297,7 +407,7 @@ pub struct View {
 
     children: Vec<Pin<Box<ViewChild>>>,
 
-    pos: (f32, f32),
+    rect: Rect,
 
     _pin: PhantomPinned,
 }
@@ -311,7 +421,7 @@ impl View {
             id,
             shell_surface: ShellView::Empty,
             children: Vec::new(),
-            pos: (0.0, 0.0),
+            rect: Rect::default(),
 
             _pin: PhantomPinned,
         });
@@ -323,6 +433,19 @@ impl View {
         view
     }
 
+    /// View without a surface, for window manager tests
+    #[cfg(test)]
+    pub fn empty(id: NodeId) -> Pin<Box<Self>> {
+        Box::pin(View {
+            id,
+            shell_surface: ShellView::Empty,
+            children: Vec::new(),
+            rect: Rect::default(),
+
+            _pin: PhantomPinned,
+        })
+    }
+
     unsafe fn configure_listeners(&mut self) {
         match &mut self.shell_surface {
             ShellView::Empty => (),
@@ -330,13 +453,38 @@ impl View {
         }
     }
 
-    pub fn configure_rect(self: Pin<&mut Self>, rect: &Rect) {
+    pub fn configure_rect(self: Pin<&mut Self>, rect: &Rect, state: WindowState, maximized: bool) {
         self.shell_surface
-            .configure_size(rect.w.round() as u32, rect.h.round() as u32);
+            .configure(rect.w.round() as u32, rect.h.round() as u32, state, maximized);
 
         unsafe {
             let borrowed = self.get_unchecked_mut();
-            borrowed.pos = (rect.x, rect.y);
+            borrowed.rect = rect.clone();
+        }
+    }
+
+    /// Where the view is, as last configured
+    pub fn rect(&self) -> &Rect {
+        &self.rect
+    }
+
+    /// Size the client would like to have. When mapping, that's the size it chose for itself.
+    pub fn preferred_size(&self) -> Option<(f32, f32)> {
+        match &self.shell_surface {
+            ShellView::Empty => None,
+            ShellView::Xdg(xdgview) => unsafe {
+                let mut geometry = MaybeUninit::<wl::wlr_box>::uninit();
+                wl::wlr_xdg_surface_get_geometry(
+                    xdgview.xdgsurface.xdg_surface,
+                    geometry.as_mut_ptr(),
+                );
+                let geometry = geometry.assume_init();
+                if geometry.width > 0 && geometry.height > 0 {
+                    Some((geometry.width as f32, geometry.height as f32))
+                } else {
+                    None
+                }
+            },
         }
     }
 }
@@ -346,6 +494,9 @@ pub struct XdgView {
     toplevel: *mut wl::wlr_xdg_toplevel,
     request_move: wl::wl_listener,
     request_resize: wl::wl_listener,
+    request_fullscreen: wl::wl_listener,
+    request_maximize: wl::wl_listener,
+    request_minimize: wl::wl_listener,
 
     _pin: PhantomPinned,
 }
@@ -360,6 +511,9 @@ impl XdgView {
 
             request_move: new_wl_listener(Some(xdg_view_request_move)),
             request_resize: new_wl_listener(Some(xdg_view_request_resize)),
+            request_fullscreen: new_wl_listener(Some(xdg_view_request_fullscreen)),
+            request_maximize: new_wl_listener(Some(xdg_view_request_maximize)),
+            request_minimize: new_wl_listener(Some(xdg_view_request_minimize)),
 
             _pin: PhantomPinned,
         }
@@ -372,6 +526,21 @@ impl XdgView {
         let x = &mut *self.toplevel;
         signal_add(&mut x.events.request_move, &mut self.request_move);
         signal_add(&mut x.events.request_resize, &mut self.request_resize);
+        signal_add(&mut x.events.request_fullscreen, &mut self.request_fullscreen);
+        signal_add(&mut x.events.request_maximize, &mut self.request_maximize);
+        signal_add(&mut x.events.request_minimize, &mut self.request_minimize);
+    }
+}
+
+impl Drop for XdgView {
+    fn drop(&mut self) {
+        unsafe {
+            wl::wl_list_remove(&mut self.request_move.link);
+            wl::wl_list_remove(&mut self.request_resize.link);
+            wl::wl_list_remove(&mut self.request_fullscreen.link);
+            wl::wl_list_remove(&mut self.request_maximize.link);
+            wl::wl_list_remove(&mut self.request_minimize.link);
+        }
     }
 }
 unsafe extern "C" fn xdg_view_request_move(_listener: *mut wl::wl_listener, _: *mut c_void) {
@@ -380,6 +549,29 @@ unsafe extern "C" fn xdg_view_request_move(_listener: *mut wl::wl_listener, _: *
 unsafe extern "C" fn xdg_view_request_resize(_listener: *mut wl::wl_listener, _: *mut c_void) {
     println!("requested resize but we don't care");
 }
+unsafe extern "C" fn xdg_view_request_fullscreen(listener: *mut wl::wl_listener, _: *mut c_void) {
+    let it = &mut *container_of!(XdgView, request_fullscreen, listener);
+    let view_id = (*it.xdgsurface.surface.view).id;
+    let fullscreen = (*it.toplevel).requested.fullscreen;
+
+    println!("{} requested fullscreen {}", view_id, fullscreen);
+    (*server_ptr()).handle_fullscreen_request(it.xdgsurface.xdg_surface, view_id, fullscreen);
+}
+unsafe extern "C" fn xdg_view_request_maximize(listener: *mut wl::wl_listener, _: *mut c_void) {
+    let it = &mut *container_of!(XdgView, request_maximize, listener);
+    let view_id = (*it.xdgsurface.surface.view).id;
+    let maximized = (*it.toplevel).requested.maximized;
+
+    println!("{} requested maximize {}", view_id, maximized);
+    (*server_ptr()).handle_maximize_request(it.xdgsurface.xdg_surface, view_id, maximized);
+}
+unsafe extern "C" fn xdg_view_request_minimize(listener: *mut wl::wl_listener, _: *mut c_void) {
+    let it = &mut *container_of!(XdgView, request_minimize, listener);
+    let view_id = (*it.xdgsurface.surface.view).id;
+
+    println!("{} requested minimize", view_id);
+    (*server_ptr()).handle_minimize_request(view_id);
+}
 
 pub struct XdgSurface {
     pub surface: Surface,
@@ -437,7 +629,12 @@ unsafe extern "C" fn xdg_surface_map(listener: *mut wl::wl_listener, _: *mut c_v
 
     println!("Mapped {}", view.id);
 
-    damage_view(&*server_ptr(), view, true);
+    let server = &mut *server_ptr();
+    if matches!(it.surface.destroy_behaviour, SurfaceBehavior::Toplevel) {
+        server.map_toplevel(view.id, it.xdg_surface);
+    }
+
+    damage_view(server, view, true);
 }
 unsafe extern "C" fn xdg_surface_unmap(listener: *mut wl::wl_listener, _: *mut c_void) {
     let it = &mut *container_of!(XdgSurface, unmap, listener);
@@ -521,9 +718,29 @@ unsafe extern "C" fn surface_destroy(listener: *mut wl::wl_listener, _: *mut c_v
         SurfaceBehavior::Toplevel => {
             let server = &mut *server_ptr();
             println!("Top level surface destroyed");
-            if let Err(e) = server.wm.remove_node(view.id) {
+            let id = view.id;
+            let focused = server.wm.active_node().map(|n| n.id) == Some(id);
+            let replacement = match server.settings.focus_on_close {
+                FocusOnClose::Mru => None,
+                FocusOnClose::Sibling => server.wm.replacement_of(id),
+            };
+
+            server.forget_pending_rules(id);
+
+            // Drops the view, and this surface with it
+            if let Err(e) = server.wm.remove_node(id) {
                 panic!("Remove node failed! {}", e);
             }
+
+            // Keyboard goes to the next window instead of nowhere
+            if focused {
+                wl::wlr_seat_keyboard_clear_focus(server.seat);
+                if let Some(next) = replacement {
+                    server.wm.touch_node(next);
+                }
+                server.refocus();
+            }
+            server.layout_changed();
         }
         SurfaceBehavior::Child => {
             let wlr_surface = it.surface;
@@ -602,8 +819,8 @@ fn damage_view(server: &Server, view: &mut View, full: bool) {
             ShellView::Xdg(v) => {
                 let xdg_surface = v.xdgsurface.xdg_surface;
                 xdg_surface_for_each_surface(xdg_surface, |s, x, y| {
-                    let ox = o.x + view.pos.0 as f64 + x as f64;
-                    let oy = o.y + view.pos.1 as f64 + y as f64;
+                    let ox = o.x + view.rect.x as f64 + x as f64;
+                    let oy = o.y + view.rect.y as f64 + y as f64;
                     damage_surface_at(output, s, ox, oy, full);
                 });
             }
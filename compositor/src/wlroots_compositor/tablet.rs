@@ -0,0 +1,383 @@
+// Drawing tablets with tablet-v2. The cursor follows the tool, and clients that don't do tablets
+// get pointer events instead: the tip is the left button and the stylus buttons right and middle.
+
+use std::ptr;
+
+use wl_sys as wl;
+
+use super::runner::{cursor_pos, find_window, focus_at, handle_cursor_move};
+use super::server::{listen_server_signal, Listener, Server};
+
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+const BTN_STYLUS: u32 = 0x14b;
+const BTN_STYLUS2: u32 = 0x14c;
+
+pub struct Tablet {
+    pub device: *mut wl::wlr_input_device,
+    pub tablet_v2: *mut wl::wlr_tablet_v2_tablet,
+    pub destroy: Listener<(), u8>,
+    pub id: u8,
+}
+
+pub struct TabletPad {
+    pub device: *mut wl::wlr_input_device,
+    pub pad_v2: *mut wl::wlr_tablet_v2_tablet_pad,
+    pub button: Listener<wl::wlr_event_tablet_pad_button, u8>,
+    pub ring: Listener<wl::wlr_event_tablet_pad_ring, u8>,
+    pub strip: Listener<wl::wlr_event_tablet_pad_strip, u8>,
+    pub destroy: Listener<(), u8>,
+    pub id: u8,
+    /// Only compared, might be gone already
+    focus: *mut wl::wlr_surface,
+}
+
+/// Pen, eraser and such. Made when they are first seen.
+pub struct TabletTool {
+    pub tool: *mut wl::wlr_tablet_tool,
+    pub tool_v2: *mut wl::wlr_tablet_v2_tablet_tool,
+    pub destroy: Listener<(), u8>,
+    pub id: u8,
+    tilt: (f64, f64),
+    /// Tip is down as the left button
+    emulating_tip: bool,
+}
+
+impl Server {
+    pub unsafe fn add_tablet(&mut self, device: *mut wl::wlr_input_device) {
+        let Some(id) = (1..255).find(|i| self.tablets.iter().all(|x| x.id != *i)) else {
+            eprintln!("Can't add tablet, too many of them");
+            return;
+        };
+        let mut tablet = Box::pin(Tablet {
+            device,
+            tablet_v2: wl::wlr_tablet_create(self.tablet_manager, self.seat, device),
+            destroy: Listener::new(tablet_destroy, id),
+            id,
+        });
+        let t = tablet.as_mut().get_unchecked_mut();
+        listen_server_signal(&mut (*device).events.destroy, &mut t.destroy);
+        wl::wlr_cursor_attach_input_device(self.cursor, device);
+
+        println!("Add tablet");
+        self.tablets.push(tablet);
+        self.map_to_outputs();
+    }
+
+    pub unsafe fn add_tablet_pad(&mut self, device: *mut wl::wlr_input_device) {
+        let Some(id) = (1..255).find(|i| self.tablet_pads.iter().all(|x| x.id != *i)) else {
+            eprintln!("Can't add tablet pad, too many of them");
+            return;
+        };
+        let mut pad = Box::pin(TabletPad {
+            device,
+            pad_v2: wl::wlr_tablet_pad_create(self.tablet_manager, self.seat, device),
+            button: Listener::new(pad_button, id),
+            ring: Listener::new(pad_ring, id),
+            strip: Listener::new(pad_strip, id),
+            destroy: Listener::new(pad_destroy, id),
+            id,
+            focus: ptr::null_mut(),
+        });
+        let p = pad.as_mut().get_unchecked_mut();
+        let events = &mut (*(*device).__bindgen_anon_1.tablet_pad).events;
+        listen_server_signal(&mut events.button, &mut p.button);
+        listen_server_signal(&mut events.ring, &mut p.ring);
+        listen_server_signal(&mut events.strip, &mut p.strip);
+        listen_server_signal(&mut (*device).events.destroy, &mut p.destroy);
+
+        println!("Add tablet pad");
+        self.tablet_pads.push(pad);
+    }
+
+    /// Index of the tool in tablet_tools
+    unsafe fn tablet_tool(&mut self, tool: *mut wl::wlr_tablet_tool) -> Option<usize> {
+        if let Some(i) = self.tablet_tools.iter().position(|t| t.tool == tool) {
+            return Some(i);
+        }
+        let id = (1..255).find(|i| self.tablet_tools.iter().all(|x| x.id != *i))?;
+        let mut t = Box::pin(TabletTool {
+            tool,
+            tool_v2: wl::wlr_tablet_tool_create(self.tablet_manager, self.seat, tool),
+            destroy: Listener::new(tool_destroy, id),
+            id,
+            tilt: (0.0, 0.0),
+            emulating_tip: false,
+        });
+        let x = t.as_mut().get_unchecked_mut();
+        listen_server_signal(&mut (*tool).events.destroy, &mut x.destroy);
+        self.tablet_tools.push(t);
+        Some(self.tablet_tools.len() - 1)
+    }
+
+    /// Cursor is already where the tool is. Tells the client under it about the tool, or moves
+    /// the pointer if the client doesn't do tablets.
+    unsafe fn tablet_tool_moved(
+        &mut self,
+        device: *mut wl::wlr_input_device,
+        tool: usize,
+        time: u32,
+    ) {
+        let tool_v2 = self.tablet_tools[tool].tool_v2;
+        let tablet_v2 = match self.tablets.iter().find(|t| t.device == device) {
+            Some(t) => t.tablet_v2,
+            None => return,
+        };
+
+        let found = find_window(self, cursor_pos(self)).map(|(_, surface, p)| (surface, p));
+        let tablet_surface = match found {
+            Some((surface, p)) if !self.tablet_tools[tool].emulating_tip => {
+                wl::wlr_surface_accepts_tablet_v2(tablet_v2, surface).then(|| (surface, p))
+            }
+            _ => None,
+        };
+
+        let Some((surface, p)) = tablet_surface else {
+            if !(*tool_v2).focused_surface.is_null() {
+                wl::wlr_tablet_v2_tablet_tool_notify_proximity_out(tool_v2);
+            }
+            handle_cursor_move(self, time);
+            return;
+        };
+
+        let focused = (*tool_v2).focused_surface;
+        if !focused.is_null() && focused != surface {
+            wl::wlr_tablet_v2_tablet_tool_notify_proximity_out(tool_v2);
+        }
+        wl::wlr_tablet_v2_tablet_tool_notify_proximity_in(tool_v2, tablet_v2, surface);
+        wl::wlr_tablet_v2_tablet_tool_notify_motion(tool_v2, p.x, p.y);
+
+        // Pads go where the pen is
+        for pad in self.tablet_pads.iter_mut() {
+            if pad.focus != surface {
+                let pad = pad.as_mut().get_unchecked_mut();
+                pad.focus = surface;
+                wl::wlr_tablet_v2_tablet_pad_notify_enter(pad.pad_v2, tablet_v2, surface);
+            }
+        }
+    }
+
+    /// Tablet surface has the tool, otherwise it's the pointer
+    unsafe fn tool_on_surface(&self, tool: usize) -> bool {
+        let t = &self.tablet_tools[tool];
+        !t.emulating_tip && !(*t.tool_v2).focused_surface.is_null()
+    }
+}
+
+fn pointer_button(server: &Server, time: u32, button: u32, state: wl::wlr_button_state) {
+    unsafe {
+        wl::wlr_seat_pointer_notify_button(server.seat, time, button, state);
+        wl::wlr_seat_pointer_notify_frame(server.seat);
+    }
+}
+
+pub fn tool_proximity(server: &mut Server, event: &mut wl::wlr_event_tablet_tool_proximity, _: ()) {
+    unsafe {
+        let Some(tool) = server.tablet_tool(event.tool) else {
+            return;
+        };
+        if event.state == wl::wlr_tablet_tool_proximity_state_WLR_TABLET_TOOL_PROXIMITY_OUT {
+            wl::wlr_tablet_v2_tablet_tool_notify_proximity_out(server.tablet_tools[tool].tool_v2);
+            return;
+        }
+        wl::wlr_cursor_warp_absolute(server.cursor, event.device, event.x, event.y);
+        server.tablet_tool_moved(event.device, tool, event.time_msec);
+    }
+}
+
+pub fn tool_axis(server: &mut Server, event: &mut wl::wlr_event_tablet_tool_axis, _: ()) {
+    unsafe {
+        let Some(tool) = server.tablet_tool(event.tool) else {
+            return;
+        };
+        let axes = event.updated_axes;
+        let has = |axis: u32| axes & axis != 0;
+
+        // NAN leaves that one as it is
+        let x = if has(wl::wlr_tablet_tool_axes_WLR_TABLET_TOOL_AXIS_X) {
+            event.x
+        } else {
+            f64::NAN
+        };
+        let y = if has(wl::wlr_tablet_tool_axes_WLR_TABLET_TOOL_AXIS_Y) {
+            event.y
+        } else {
+            f64::NAN
+        };
+        wl::wlr_cursor_warp_absolute(server.cursor, event.device, x, y);
+        server.tablet_tool_moved(event.device, tool, event.time_msec);
+
+        let t = server.tablet_tools[tool].as_mut().get_unchecked_mut();
+        if has(wl::wlr_tablet_tool_axes_WLR_TABLET_TOOL_AXIS_TILT_X) {
+            t.tilt.0 = event.tilt_x;
+        }
+        if has(wl::wlr_tablet_tool_axes_WLR_TABLET_TOOL_AXIS_TILT_Y) {
+            t.tilt.1 = event.tilt_y;
+        }
+        if !server.tool_on_surface(tool) {
+            return;
+        }
+
+        let tool_v2 = server.tablet_tools[tool].tool_v2;
+        if has(wl::wlr_tablet_tool_axes_WLR_TABLET_TOOL_AXIS_PRESSURE) {
+            wl::wlr_tablet_v2_tablet_tool_notify_pressure(tool_v2, event.pressure);
+        }
+        if has(wl::wlr_tablet_tool_axes_WLR_TABLET_TOOL_AXIS_DISTANCE) {
+            wl::wlr_tablet_v2_tablet_tool_notify_distance(tool_v2, event.distance);
+        }
+        if has(wl::wlr_tablet_tool_axes_WLR_TABLET_TOOL_AXIS_TILT_X)
+            || has(wl::wlr_tablet_tool_axes_WLR_TABLET_TOOL_AXIS_TILT_Y)
+        {
+            let (tilt_x, tilt_y) = server.tablet_tools[tool].tilt;
+            wl::wlr_tablet_v2_tablet_tool_notify_tilt(tool_v2, tilt_x, tilt_y);
+        }
+        if has(wl::wlr_tablet_tool_axes_WLR_TABLET_TOOL_AXIS_ROTATION) {
+            wl::wlr_tablet_v2_tablet_tool_notify_rotation(tool_v2, event.rotation);
+        }
+        if has(wl::wlr_tablet_tool_axes_WLR_TABLET_TOOL_AXIS_SLIDER) {
+            wl::wlr_tablet_v2_tablet_tool_notify_slider(tool_v2, event.slider);
+        }
+        if has(wl::wlr_tablet_tool_axes_WLR_TABLET_TOOL_AXIS_WHEEL) {
+            wl::wlr_tablet_v2_tablet_tool_notify_wheel(tool_v2, event.wheel_delta, 0);
+        }
+    }
+}
+
+pub fn tool_tip(server: &mut Server, event: &mut wl::wlr_event_tablet_tool_tip, _: ()) {
+    unsafe {
+        let Some(tool) = server.tablet_tool(event.tool) else {
+            return;
+        };
+        let down = event.state == wl::wlr_tablet_tool_tip_state_WLR_TABLET_TOOL_TIP_DOWN;
+        if down {
+            focus_at(server, cursor_pos(server));
+        }
+
+        let tool_v2 = server.tablet_tools[tool].tool_v2;
+        if server.tool_on_surface(tool) {
+            if down {
+                wl::wlr_tablet_v2_tablet_tool_notify_down(tool_v2);
+                wl::wlr_tablet_tool_v2_start_implicit_grab(tool_v2);
+            } else {
+                wl::wlr_tablet_v2_tablet_tool_notify_up(tool_v2);
+            }
+            return;
+        }
+
+        let t = server.tablet_tools[tool].as_mut().get_unchecked_mut();
+        if down {
+            t.emulating_tip = true;
+            pointer_button(
+                server,
+                event.time_msec,
+                BTN_LEFT,
+                wl::wlr_button_state_WLR_BUTTON_PRESSED,
+            );
+        } else if t.emulating_tip {
+            t.emulating_tip = false;
+            pointer_button(
+                server,
+                event.time_msec,
+                BTN_LEFT,
+                wl::wlr_button_state_WLR_BUTTON_RELEASED,
+            );
+        }
+    }
+}
+
+pub fn tool_button(server: &mut Server, event: &mut wl::wlr_event_tablet_tool_button, _: ()) {
+    unsafe {
+        let Some(tool) = server.tablet_tool(event.tool) else {
+            return;
+        };
+        if server.tool_on_surface(tool) {
+            // Same values for pressed and released
+            wl::wlr_tablet_v2_tablet_tool_notify_button(
+                server.tablet_tools[tool].tool_v2,
+                event.button,
+                event.state as wl::zwp_tablet_pad_v2_button_state,
+            );
+            return;
+        }
+        let button = match event.button {
+            BTN_STYLUS => BTN_RIGHT,
+            BTN_STYLUS2 => BTN_MIDDLE,
+            _ => return,
+        };
+        pointer_button(server, event.time_msec, button, event.state);
+    }
+}
+
+fn pad_button(server: &mut Server, event: &mut wl::wlr_event_tablet_pad_button, id: u8) {
+    let Some(pad) = server.tablet_pads.iter().find(|p| p.id == id) else {
+        return;
+    };
+    unsafe {
+        wl::wlr_tablet_v2_tablet_pad_notify_mode(
+            pad.pad_v2,
+            event.group as usize,
+            event.mode,
+            event.time_msec,
+        );
+        wl::wlr_tablet_v2_tablet_pad_notify_button(
+            pad.pad_v2,
+            event.button as usize,
+            event.time_msec,
+            event.state as wl::zwp_tablet_pad_v2_button_state,
+        );
+    }
+}
+
+fn pad_ring(server: &mut Server, event: &mut wl::wlr_event_tablet_pad_ring, id: u8) {
+    let Some(pad) = server.tablet_pads.iter().find(|p| p.id == id) else {
+        return;
+    };
+    let finger = event.source == wl::wlr_tablet_pad_ring_source_WLR_TABLET_PAD_RING_SOURCE_FINGER;
+    unsafe {
+        wl::wlr_tablet_v2_tablet_pad_notify_ring(
+            pad.pad_v2,
+            event.ring,
+            event.position,
+            finger,
+            event.time_msec,
+        );
+    }
+}
+
+fn pad_strip(server: &mut Server, event: &mut wl::wlr_event_tablet_pad_strip, id: u8) {
+    let Some(pad) = server.tablet_pads.iter().find(|p| p.id == id) else {
+        return;
+    };
+    let finger = event.source == wl::wlr_tablet_pad_strip_source_WLR_TABLET_PAD_STRIP_SOURCE_FINGER;
+    unsafe {
+        wl::wlr_tablet_v2_tablet_pad_notify_strip(
+            pad.pad_v2,
+            event.strip,
+            event.position,
+            finger,
+            event.time_msec,
+        );
+    }
+}
+
+// The v2 objects clean up after themselves when the devices go
+
+fn tablet_destroy(server: &mut Server, _: &mut (), id: u8) {
+    if let Some(i) = server.tablets.iter().position(|x| x.id == id) {
+        let tablet = server.tablets.remove(i);
+        println!("Remove tablet");
+        unsafe {
+            wl::wlr_cursor_detach_input_device(server.cursor, tablet.device);
+        }
+    }
+}
+
+fn pad_destroy(server: &mut Server, _: &mut (), id: u8) {
+    println!("Remove tablet pad");
+    server.tablet_pads.retain(|x| x.id != id);
+}
+
+fn tool_destroy(server: &mut Server, _: &mut (), id: u8) {
+    server.tablet_tools.retain(|x| x.id != id);
+}
@@ -0,0 +1,121 @@
+// Touchscreens. Touches go to the surface they start on, clients that don't do touch get a pointer
+// that clicks with the first finger.
+
+use wl_sys as wl;
+
+use super::runner::{find_window, focus_at, handle_cursor_move};
+use super::server::Server;
+use super::wl_util::Point;
+
+const BTN_LEFT: u32 = 0x110;
+
+/// Touch point that is down on a surface
+pub struct TouchPoint {
+    pub id: i32,
+    /// Where the surface is in layout coordinates
+    pub origin: (f64, f64),
+}
+
+/// Touch event coordinates are from 0 to 1 on the output the device is mapped to
+unsafe fn touch_pos(server: &Server, device: *mut wl::wlr_input_device, x: f64, y: f64) -> Point {
+    let mut pos = Point { x: 0.0, y: 0.0 };
+    wl::wlr_cursor_absolute_to_layout_coords(server.cursor, device, x, y, &mut pos.x, &mut pos.y);
+    pos
+}
+
+pub fn touch_down(server: &mut Server, event: &mut wl::wlr_event_touch_down, _: ()) {
+    unsafe {
+        let pos = touch_pos(server, event.device, event.x, event.y);
+        let found = find_window(server, pos).map(|(_, surface, p)| (surface, p));
+        let Some((surface, p)) = found else {
+            return;
+        };
+
+        // Touching a window focuses it, like clicking
+        focus_at(server, pos);
+
+        if wl::wlr_surface_accepts_touch(server.seat, surface) {
+            wl::wlr_seat_touch_notify_down(
+                server.seat,
+                surface,
+                event.time_msec,
+                event.touch_id,
+                p.x,
+                p.y,
+            );
+            server.touch_points.push(TouchPoint {
+                id: event.touch_id,
+                origin: (pos.x - p.x, pos.y - p.y),
+            });
+        } else if server.touch_emulation.is_none() {
+            server.touch_emulation = Some(event.touch_id);
+            wl::wlr_cursor_warp_absolute(server.cursor, event.device, event.x, event.y);
+            handle_cursor_move(server, event.time_msec);
+            wl::wlr_seat_pointer_notify_button(
+                server.seat,
+                event.time_msec,
+                BTN_LEFT,
+                wl::wlr_button_state_WLR_BUTTON_PRESSED,
+            );
+        }
+    }
+}
+
+pub fn touch_motion(server: &mut Server, event: &mut wl::wlr_event_touch_motion, _: ()) {
+    unsafe {
+        if server.touch_emulation == Some(event.touch_id) {
+            wl::wlr_cursor_warp_absolute(server.cursor, event.device, event.x, event.y);
+            handle_cursor_move(server, event.time_msec);
+            return;
+        }
+
+        // Stays on the surface it started on, even when it goes outside of it
+        let pos = touch_pos(server, event.device, event.x, event.y);
+        if let Some(point) = server.touch_points.iter().find(|t| t.id == event.touch_id) {
+            let (x, y) = point.origin;
+            wl::wlr_seat_touch_notify_motion(
+                server.seat,
+                event.time_msec,
+                event.touch_id,
+                pos.x - x,
+                pos.y - y,
+            );
+        }
+    }
+}
+
+pub fn touch_up(server: &mut Server, event: &mut wl::wlr_event_touch_up, _: ()) {
+    end_touch(server, event.touch_id, event.time_msec);
+}
+
+/// The seat can't cancel touches (yet), so this is the same as lifting the finger
+pub fn touch_cancel(server: &mut Server, event: &mut wl::wlr_event_touch_cancel, _: ()) {
+    end_touch(server, event.touch_id, event.time_msec);
+}
+
+pub fn touch_frame(server: &mut Server, _: &mut (), _: ()) {
+    unsafe {
+        if server.touch_emulation.is_some() {
+            wl::wlr_seat_pointer_notify_frame(server.seat);
+        }
+        wl::wlr_seat_touch_notify_frame(server.seat);
+    }
+}
+
+fn end_touch(server: &mut Server, touch_id: i32, time: u32) {
+    unsafe {
+        if server.touch_emulation == Some(touch_id) {
+            server.touch_emulation = None;
+            wl::wlr_seat_pointer_notify_button(
+                server.seat,
+                time,
+                BTN_LEFT,
+                wl::wlr_button_state_WLR_BUTTON_RELEASED,
+            );
+            wl::wlr_seat_pointer_notify_frame(server.seat);
+        } else if let Some(i) = server.touch_points.iter().position(|t| t.id == touch_id) {
+            server.touch_points.remove(i);
+            wl::wlr_seat_touch_notify_up(server.seat, time, touch_id);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::ffi::c_void;
+use std::mem::MaybeUninit;
 use std::os::raw::c_int;
 use std::ptr;
 
@@ -79,6 +80,17 @@ impl Drop for ListenerWrapper {
     }
 }
 
+/// Same clock as the times in input events
+pub fn now_msec() -> u32 {
+    let mut now = MaybeUninit::uninit();
+    let now = unsafe {
+        wl::clock_gettime(wl::CLOCK_MONOTONIC as i32, now.as_mut_ptr());
+        now.assume_init()
+    };
+    (now.tv_sec as i64 * 1000 + now.tv_nsec as i64 / 1_000_000) as u32
+}
+
+#[derive(Clone, Copy)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
@@ -0,0 +1,63 @@
+// Send a command to rspwl, like bspc does for bspwm
+
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::exit;
+
+const FAILURE_MESSAGE: char = '\x07';
+
+fn socket_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("RSPWL_SOCKET") {
+        return Some(path.into());
+    }
+    let display = std::env::var("WAYLAND_DISPLAY").ok()?;
+    let dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_string());
+    Some(PathBuf::from(dir).join(format!("rspwl-{}.sock", display)))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() {
+        eprintln!("usage: rspc COMMAND [ARGUMENTS...]");
+        exit(1);
+    }
+
+    let Some(path) = socket_path() else {
+        eprintln!("Don't know where rspwl is. Set RSPWL_SOCKET or WAYLAND_DISPLAY.");
+        exit(1);
+    };
+
+    let mut stream = match UnixStream::connect(&path) {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("Failed to connect to {}: {}", path.display(), e);
+            exit(1);
+        }
+    };
+
+    let mut msg = Vec::new();
+    for arg in args {
+        msg.extend_from_slice(arg.as_bytes());
+        msg.push(0);
+    }
+
+    let mut reply = String::new();
+    let sent = stream
+        .write_all(&msg)
+        .and_then(|_| stream.shutdown(Shutdown::Write))
+        .and_then(|_| stream.read_to_string(&mut reply));
+    if let Err(e) = sent {
+        eprintln!("Failed to talk to rspwl: {}", e);
+        exit(1);
+    }
+
+    match reply.strip_prefix(FAILURE_MESSAGE) {
+        Some(failure) => {
+            eprint!("{}", failure);
+            exit(1);
+        }
+        None => print!("{}", reply),
+    }
+}
@@ -14,6 +14,7 @@ fn main() {
         "xkbcommon",
         "pixman-1",
         "wlroots",
+        "libinput",
     ];
 
     let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
@@ -48,12 +49,30 @@ fn main() {
 
     cc::Build::new().file(&c_code).compile("xdg-shell-protocol");
 
+    // wlroots headers want these, the code is in wlroots
+    for (dir, name) in [
+        ("unstable/tablet", "tablet-unstable-v2"),
+        ("unstable/pointer-constraints", "pointer-constraints-unstable-v1"),
+    ] {
+        let ok = Command::new("wayland-scanner")
+            .arg("server-header")
+            .arg(format!("{}/{}/{}.xml", protocol_dir, dir, name))
+            .arg(&out_path.join(format!("{}-protocol.h", name)))
+            .status()
+            .unwrap()
+            .success();
+        if !ok {
+            panic!("wayland-scanner failed");
+        }
+    }
+
     let builder = bindgen::builder()
         .header("wrapper.h")
         .parse_callbacks(Box::new(BindgenWorkaround {}))
         .allowlist_function("wl.*")
         .allowlist_function("pixman.*")
         .allowlist_function("xkb_.*")
+        .allowlist_function("libinput_device_config.*")
         // bit hairy, but deal with it
         .allowlist_function("clock_gettime")
         .allowlist_var("CLOCK_MONOTONIC")
@@ -61,6 +80,7 @@ fn main() {
         .allowlist_type("wl.*")
         .allowlist_type("pixman.*")
         .allowlist_type("xkb_.*")
+        .allowlist_type("libinput.*")
         .clang_arg(format!("-I{}", out_path.display()))
         .clang_args(
             pkg_config_args(&libs, "--cflags")